@@ -0,0 +1,177 @@
+//! Amplifier circuits of several computers running one program, as in problem 7.
+use crate::computer::{ComputerError, Machine, State, Word};
+use crate::program::Program;
+use std::collections::VecDeque;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Topology {
+    /// Each amplifier feeds the next and the last one's output is the thruster signal.
+    Chain,
+    /// As a chain, but the last amplifier also feeds the first until every amplifier halts.
+    Feedback
+}
+
+#[derive(Debug)]
+pub enum AmplifierError {
+    Computer { amplifier: usize, error: ComputerError },
+    Deadlock,
+    NoSignal
+}
+
+impl fmt::Display for AmplifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AmplifierError::Computer { amplifier, error } => write!(f, "Amplifier {} failed: {}", amplifier, error),
+            AmplifierError::Deadlock => write!(f, "Every running amplifier is waiting for input."),
+            AmplifierError::NoSignal => write!(f, "The last amplifier halted without producing a signal.")
+        }
+    }
+}
+
+impl std::error::Error for AmplifierError {}
+
+type Amplifier<W> = Machine<W, VecDeque<W>, VecDeque<W>>;
+
+// Runs one amplifier until it blocks or halts, passing its outputs along. Returns whether it produced any.
+fn run_amplifier<W: Word>(
+    amplifiers: &mut [Amplifier<W>],
+    index: usize,
+    topology: Topology,
+    signal: &mut Option<W>
+) -> Result<bool, AmplifierError> {
+    let last = amplifiers.len() - 1;
+    let mut produced = false;
+
+    loop {
+        let state = amplifiers[index].run_until_io()
+            .map_err(|error| AmplifierError::Computer { amplifier: index, error })?;
+
+        match state {
+            State::Output(value) => {
+                produced = true;
+
+                if index == last {
+                    *signal = Some(value);
+                }
+
+                match (index == last, topology) {
+                    (false, _) => amplifiers[index + 1].provide_input(value),
+                    (true, Topology::Feedback) => amplifiers[0].provide_input(value),
+                    (true, Topology::Chain) => ()
+                }
+            },
+            State::NeedsInput | State::Terminated(_) => return Ok(produced),
+            State::Running | State::Paused => ()
+        }
+    }
+}
+
+/// Seeds one amplifier per phase setting, sends `input` to the first and returns the thruster signal.
+pub fn run<W: Word>(program: &Program<W>, phases: &[W], input: W, topology: Topology) -> Result<W, AmplifierError> {
+    if phases.is_empty() {
+        return Err(AmplifierError::NoSignal);
+    }
+
+    let mut amplifiers: Vec<Amplifier<W>> = phases.iter().map(|phase| {
+        let mut amplifier = Machine::from_program(program, VecDeque::new(), VecDeque::new());
+        amplifier.provide_input(*phase);
+        amplifier
+    }).collect();
+    amplifiers[0].provide_input(input);
+
+    let mut signal = None;
+
+    while amplifiers.iter().any(|amplifier| amplifier.termination().is_none()) {
+        let mut produced = false;
+
+        for index in 0..amplifiers.len() {
+            produced |= run_amplifier(&mut amplifiers, index, topology, &mut signal)?;
+        }
+
+        let running = amplifiers.iter().any(|amplifier| amplifier.termination().is_none());
+        if running && !produced {
+            return Err(AmplifierError::Deadlock);
+        }
+    }
+
+    signal.ok_or(AmplifierError::NoSignal)
+}
+
+fn permutations<T: Copy>(items: &[T]) -> Vec<Vec<T>> {
+    if items.len() <= 1 {
+        return vec![items.to_vec()];
+    }
+
+    let mut result = vec![];
+    for index in 0..items.len() {
+        let mut rest = items.to_vec();
+        let first = rest.remove(index);
+
+        for mut permutation in permutations(&rest) {
+            permutation.insert(0, first);
+            result.push(permutation);
+        }
+    }
+
+    result
+}
+
+/// Tries every ordering of `phases` and returns the one producing the largest thruster signal.
+pub fn max_signal<W: Word>(program: &Program<W>, phases: &[W], topology: Topology) -> Result<(Vec<W>, W), AmplifierError> {
+    let mut best: Option<(Vec<W>, W)> = None;
+
+    for permutation in permutations(phases) {
+        let signal = run(program, &permutation, W::from(0), topology)?;
+
+        match &best {
+            Some((_, best_signal)) if *best_signal >= signal => (),
+            _ => best = Some((permutation, signal))
+        }
+    }
+
+    best.ok_or(AmplifierError::NoSignal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{max_signal, run, AmplifierError, Topology};
+    use crate::program::Program;
+
+    fn program(source: &str) -> Program<i64> {
+        source.parse().unwrap()
+    }
+
+    #[test]
+    fn chain() {
+        let program = program("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0");
+
+        assert_eq!(run(&program, &[4,3,2,1,0], 0, Topology::Chain).unwrap(), 43210);
+        assert_eq!(max_signal(&program, &[0,1,2,3,4], Topology::Chain).unwrap(), (vec![4,3,2,1,0], 43210));
+    }
+
+    #[test]
+    fn chain_search() {
+        let program = program("3,23,3,24,1002,24,10,24,1002,23,-1,23,101,5,23,23,1,24,23,23,4,23,99,0,0");
+
+        assert_eq!(max_signal(&program, &[0,1,2,3,4], Topology::Chain).unwrap(), (vec![0,1,2,3,4], 54321));
+    }
+
+    #[test]
+    fn feedback() {
+        let program = program("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5");
+
+        assert_eq!(run(&program, &[9,8,7,6,5], 0, Topology::Feedback).unwrap(), 139_629_729);
+        assert_eq!(max_signal(&program, &[5,6,7,8,9], Topology::Feedback).unwrap(), (vec![9,8,7,6,5], 139_629_729));
+    }
+
+    #[test]
+    fn failures() {
+        assert!(matches!(run(&program("3,0,3,0,99"), &[1], 0, Topology::Chain), Err(AmplifierError::NoSignal)));
+        assert!(matches!(run(&program("3,0,3,0,3,0,99"), &[1, 2], 0, Topology::Chain), Err(AmplifierError::Deadlock)));
+        assert!(matches!(
+            run(&program("3,0,3,0,4,0,42"), &[1, 2], 0, Topology::Chain),
+            Err(AmplifierError::Computer { amplifier: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,368 @@
+//! Assembler turning mnemonic source into an Intcode image.
+//!
+//! Source uses the same syntax as the disassembler listing: `[n]` for position operands, `#n` for
+//! immediates and `[rb+n]` for relative operands, where `n` is a literal or a label. Labels end in
+//! a colon, `data` emits raw words and `;` starts a comment.
+use crate::computer::{OpCode, ParameterMode, Word};
+use crate::disassembler::mnemonic;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum AssembleErrorKind {
+    UnknownMnemonic(String),
+    OperandCount { mnemonic: String, expected: usize, found: usize },
+    InvalidOperand(String),
+    InvalidLiteral(String),
+    InvalidLabel(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub kind: AssembleErrorKind
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+
+        match &self.kind {
+            AssembleErrorKind::UnknownMnemonic(name) => write!(f, "Unknown mnemonic: [{}]", name),
+            AssembleErrorKind::OperandCount { mnemonic, expected, found } =>
+                write!(f, "{} takes {} operands, found {}", mnemonic, expected, found),
+            AssembleErrorKind::InvalidOperand(operand) => write!(f, "Invalid operand: [{}]", operand),
+            AssembleErrorKind::InvalidLiteral(literal) => write!(f, "Invalid literal: [{}]", literal),
+            AssembleErrorKind::InvalidLabel(label) => write!(f, "Invalid label: [{}]", label),
+            AssembleErrorKind::DuplicateLabel(label) => write!(f, "Duplicate label: [{}]", label),
+            AssembleErrorKind::UndefinedLabel(label) => write!(f, "Undefined label: [{}]", label)
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+enum Value<W: Word> {
+    Literal(W),
+    Label(String)
+}
+
+struct Operand<W: Word> {
+    mode: ParameterMode,
+    value: Value<W>
+}
+
+enum Item<W: Word> {
+    Instruction {
+        opcode: OpCode,
+        operands: Vec<Operand<W>>
+    },
+    Data(Vec<Value<W>>)
+}
+
+struct Statement<W: Word> {
+    line: usize,
+    item: Item<W>
+}
+
+const OPCODES: [i32; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+
+fn find_opcode(name: &str) -> Option<OpCode> {
+    OPCODES.iter()
+        .filter_map(|code| OpCode::try_from(*code).ok())
+        .find(|opcode| mnemonic(opcode).eq_ignore_ascii_case(name))
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => (),
+        _ => return false
+    }
+
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_') && !name.eq_ignore_ascii_case("rb")
+}
+
+fn parse_value<W: Word>(text: &str) -> Result<Value<W>, AssembleErrorKind> {
+    if let Ok(value) = text.parse::<W>() {
+        return Ok(Value::Literal(value));
+    }
+
+    if is_label(text) {
+        return Ok(Value::Label(text.to_owned()));
+    }
+
+    match text.chars().next() {
+        Some(c) if c.is_ascii_digit() || c == '-' || c == '+' => Err(AssembleErrorKind::InvalidLiteral(text.to_owned())),
+        _ => Err(AssembleErrorKind::InvalidOperand(text.to_owned()))
+    }
+}
+
+fn parse_operand<W: Word>(text: &str) -> Result<Operand<W>, AssembleErrorKind> {
+    if let Some(immediate) = text.strip_prefix('#') {
+        return Ok(Operand { mode: ParameterMode::Immediate, value: parse_value(immediate.trim())? });
+    }
+
+    let inner = match text.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        Some(inner) => inner.trim(),
+        None => return Err(AssembleErrorKind::InvalidOperand(text.to_owned()))
+    };
+
+    if inner.len() >= 2 && inner[..2].eq_ignore_ascii_case("rb") && !is_label(inner) {
+        let offset: String = inner[2..].chars().filter(|c| !c.is_whitespace()).collect();
+
+        let value = if offset.is_empty() {
+            Value::Literal(W::from(0))
+        } else if let Some(label) = offset.strip_prefix('+').filter(|label| is_label(label)) {
+            Value::Label(label.to_owned())
+        } else if offset.starts_with('+') || offset.starts_with('-') {
+            parse_value(&offset)?
+        } else {
+            return Err(AssembleErrorKind::InvalidOperand(text.to_owned()));
+        };
+
+        return Ok(Operand { mode: ParameterMode::Relative, value });
+    }
+
+    Ok(Operand { mode: ParameterMode::Position, value: parse_value(inner)? })
+}
+
+fn parse_statement<W: Word>(text: &str) -> Result<Item<W>, AssembleErrorKind> {
+    let (name, rest) = match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim()),
+        None => (text, "")
+    };
+
+    let arguments: Vec<&str> = if rest.is_empty() {
+        vec![]
+    } else {
+        rest.split(',').map(str::trim).collect()
+    };
+
+    if name.eq_ignore_ascii_case("data") {
+        if arguments.is_empty() {
+            return Err(AssembleErrorKind::InvalidOperand(text.to_owned()));
+        }
+
+        let values = arguments.iter().map(|argument| parse_value(argument)).collect::<Result<_, _>>()?;
+        return Ok(Item::Data(values));
+    }
+
+    let opcode = find_opcode(name).ok_or_else(|| AssembleErrorKind::UnknownMnemonic(name.to_owned()))?;
+    let expected = opcode.instruction_size() - 1;
+
+    if arguments.len() != expected {
+        return Err(AssembleErrorKind::OperandCount {
+            mnemonic: mnemonic(&opcode).to_owned(),
+            expected,
+            found: arguments.len()
+        });
+    }
+
+    let operands = arguments.iter().map(|argument| parse_operand(argument)).collect::<Result<_, _>>()?;
+
+    Ok(Item::Instruction { opcode, operands })
+}
+
+fn resolve<W: Word>(value: &Value<W>, labels: &HashMap<String, usize>, line: usize) -> Result<W, AssembleError> {
+    match value {
+        Value::Literal(value) => Ok(*value),
+        Value::Label(label) => match labels.get(label) {
+            Some(address) => Ok(W::from(*address as i32)),
+            None => Err(AssembleError { line, kind: AssembleErrorKind::UndefinedLabel(label.clone()) })
+        }
+    }
+}
+
+pub fn assemble<W: Word>(source: &str) -> Result<Vec<W>, AssembleError> {
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut statements: Vec<Statement<W>> = vec![];
+    let mut address = 0;
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = match raw_line.find(';') {
+            Some(comment) => raw_line[..comment].trim(),
+            None => raw_line.trim()
+        };
+
+        // Leading labels, and the numeric addresses of a disassembler listing, end in a colon.
+        while let Some(colon) = text.find(':') {
+            let name = text[..colon].trim();
+
+            if is_label(name) {
+                if labels.insert(name.to_owned(), address).is_some() {
+                    return Err(AssembleError { line, kind: AssembleErrorKind::DuplicateLabel(name.to_owned()) });
+                }
+            } else if name.is_empty() || !name.chars().all(|c| c.is_ascii_digit()) {
+                return Err(AssembleError { line, kind: AssembleErrorKind::InvalidLabel(name.to_owned()) });
+            }
+
+            text = text[colon + 1..].trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let item = parse_statement(text).map_err(|kind| AssembleError { line, kind })?;
+
+        address += match &item {
+            Item::Instruction { opcode, .. } => opcode.instruction_size(),
+            Item::Data(values) => values.len()
+        };
+
+        statements.push(Statement { line, item });
+    }
+
+    let mut image = Vec::with_capacity(address);
+
+    for Statement { line, item } in &statements {
+        match item {
+            Item::Instruction { opcode, operands } => {
+                let modes = operands.iter().zip(&[100, 1000, 10000])
+                    .map(|(operand, scale)| operand.mode.code() * scale)
+                    .sum::<i32>();
+
+                image.push(W::from(opcode.code() + modes));
+
+                for operand in operands {
+                    image.push(resolve(&operand.value, &labels, *line)?);
+                }
+            },
+            Item::Data(values) => {
+                for value in values {
+                    image.push(resolve(value, &labels, *line)?);
+                }
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble, AssembleError, AssembleErrorKind};
+    use crate::computer::Computer;
+    use crate::disassembler::listing;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn labels_and_data() {
+        let source = "
+            ; Outputs 1 if the input is 8, otherwise 0.
+            IN   [input]
+            EQ   [input], [eight], [input]
+            OUT  [input]
+            HLT
+            input: data -1
+            eight: data 8
+        ";
+
+        let mut image = assemble::<i64>(source).unwrap();
+
+        assert_eq!(image, vec![3,9,8,9,10,9,4,9,99,-1,8]);
+
+        let mut outputs = vec![];
+        let mut computer = Computer::new(&mut image, VecDeque::from(vec![8]), &mut outputs);
+        computer.compute().unwrap();
+
+        assert_eq!(outputs, vec![1]);
+    }
+
+    #[test]
+    fn relative_operands() {
+        let source = "
+            arb  #base
+            add  [rb+1], [rb-1], [rb]
+            out  [rb]
+            hlt
+            data 0
+            base: data 7, 5
+        ";
+
+        let mut image = assemble::<i64>(source).unwrap();
+
+        assert_eq!(image, vec![109,10,22201,1,-1,0,204,0,99,0,7,5]);
+
+        let mut outputs = vec![];
+        let mut computer = Computer::new(&mut image, VecDeque::new(), &mut outputs);
+        computer.compute().unwrap();
+
+        assert_eq!(outputs, vec![5]);
+    }
+
+    #[test]
+    fn forward_jumps() {
+        let source = "
+            start: jz   #0, #end
+                   out  #1
+            end:   out  #start
+                   hlt
+        ";
+
+        assert_eq!(assemble::<i32>(source).unwrap(), vec![1106,0,5,104,1,104,0,99]);
+    }
+
+    #[test]
+    fn listing_round_trip() {
+        let image = vec![3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,
+                         1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,
+                         999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99];
+
+        assert_eq!(assemble::<i32>(&listing(&image)).unwrap(), image);
+    }
+
+    #[test]
+    fn unknown_mnemonic() {
+        assert_eq!(assemble::<i32>("add #1, #2, [0]\nmov [1], [2]\n"), Err(AssembleError {
+            line: 2,
+            kind: AssembleErrorKind::UnknownMnemonic("mov".to_owned())
+        }));
+    }
+
+    #[test]
+    fn operand_count() {
+        assert_eq!(assemble::<i32>("\n\nout [1], [2]"), Err(AssembleError {
+            line: 3,
+            kind: AssembleErrorKind::OperandCount { mnemonic: "OUT".to_owned(), expected: 1, found: 2 }
+        }));
+    }
+
+    #[test]
+    fn undefined_label() {
+        assert_eq!(assemble::<i32>("out [missing]\nhlt"), Err(AssembleError {
+            line: 1,
+            kind: AssembleErrorKind::UndefinedLabel("missing".to_owned())
+        }));
+    }
+
+    #[test]
+    fn duplicate_label() {
+        assert_eq!(assemble::<i32>("here: hlt\nhere: hlt"), Err(AssembleError {
+            line: 2,
+            kind: AssembleErrorKind::DuplicateLabel("here".to_owned())
+        }));
+    }
+
+    #[test]
+    fn invalid_operands() {
+        assert_eq!(assemble::<i32>("out 5"), Err(AssembleError {
+            line: 1,
+            kind: AssembleErrorKind::InvalidOperand("5".to_owned())
+        }));
+        assert_eq!(assemble::<i32>("out #99999999999"), Err(AssembleError {
+            line: 1,
+            kind: AssembleErrorKind::InvalidLiteral("99999999999".to_owned())
+        }));
+        assert_eq!(assemble::<i32>("1st: hlt"), Err(AssembleError {
+            line: 1,
+            kind: AssembleErrorKind::InvalidLabel("1st".to_owned())
+        }));
+    }
+}
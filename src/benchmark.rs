@@ -0,0 +1,161 @@
+//! Timings of the interpreter with and without the decoded instruction cache.
+use crate::assembler::assemble;
+use crate::computer::{ComputerError, Machine};
+use crate::program::Program;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+// Counts its input down to zero in a two instruction loop.
+const COUNTDOWN: &str = "
+    in   [counter]
+loop:
+    add  [counter], #-1, [counter]
+    jnz  [counter], #loop
+    out  [counter]
+    hlt
+counter: data 0
+";
+
+// Sums i * j for every i and j below its input.
+const PRODUCTS: &str = "
+    in   [n]
+outer:
+    add  #0, #0, [j]
+inner:
+    mul  [i], [j], [t]
+    add  [sum], [t], [sum]
+    add  [j], #1, [j]
+    lt   [j], [n], [t]
+    jnz  [t], #inner
+    add  [i], #1, [i]
+    lt   [i], [n], [t]
+    jnz  [t], #outer
+    out  [sum]
+    hlt
+n:   data 0
+i:   data 0
+j:   data 0
+t:   data 0
+sum: data 0
+";
+
+// Counts down like COUNTDOWN, but rewrites its own jump target on every pass.
+const SELF_MODIFYING: &str = "
+    in   [counter]
+    arb  #jump
+loop:
+    add  [counter], #-1, [counter]
+    add  #loop, #0, [rb+2]
+jump:
+    jnz  [counter], #0
+    out  [counter]
+    hlt
+counter: data 0
+";
+
+pub struct Sample {
+    pub name: &'static str,
+    pub program: Program<i64>,
+    pub input: Vec<i64>
+}
+
+pub fn samples() -> Vec<Sample> {
+    [("countdown", COUNTDOWN, 2_000_000), ("products", PRODUCTS, 600), ("self-modifying", SELF_MODIFYING, 1_000_000)]
+        .iter()
+        .map(|(name, source, input)| Sample {
+            name,
+            program: Program::new(assemble(source).expect("Sample programs assemble.")),
+            input: vec![*input]
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct Timing {
+    pub name: String,
+    pub instructions: u64,
+    pub uncached: Duration,
+    pub cached: Duration,
+    /// Whether both runs produced the same output.
+    pub consistent: bool
+}
+
+// Each configuration is timed this many times, keeping the fastest to reduce noise.
+const RUNS: usize = 3;
+
+fn time(program: &Program<i64>, input: &[i64], cache: bool) -> Result<(Duration, u64, Vec<i64>), ComputerError> {
+    let mut fastest = (Duration::MAX, 0, vec![]);
+
+    for _ in 0..RUNS {
+        let mut outputs = vec![];
+        let mut machine = Machine::from_program(program, input.iter().copied().collect::<VecDeque<i64>>(), &mut outputs);
+        machine.set_decode_cache(cache);
+
+        let start = Instant::now();
+        machine.compute()?;
+        let elapsed = start.elapsed();
+        let steps = machine.steps();
+        drop(machine);
+
+        if elapsed < fastest.0 {
+            fastest = (elapsed, steps, outputs);
+        }
+    }
+
+    Ok(fastest)
+}
+
+pub fn measure(name: &str, program: &Program<i64>, input: &[i64]) -> Result<Timing, ComputerError> {
+    let (uncached, instructions, uncached_outputs) = time(program, input, false)?;
+    let (cached, _, cached_outputs) = time(program, input, true)?;
+
+    Ok(Timing {
+        name: name.to_owned(),
+        instructions,
+        uncached,
+        cached,
+        consistent: uncached_outputs == cached_outputs
+    })
+}
+
+pub fn report<Wr: Write>(timings: &[Timing], mut writer: Wr) -> io::Result<()> {
+    writeln!(writer, "{:<16}{:>14}{:>14}{:>14}{:>10}", "program", "instructions", "uncached", "cached", "speedup")?;
+
+    for timing in timings {
+        let speedup = timing.uncached.as_secs_f64() / timing.cached.as_secs_f64().max(f64::EPSILON);
+
+        write!(
+            writer,
+            "{:<16}{:>14}{:>14}{:>14}{:>9.2}x",
+            timing.name,
+            timing.instructions,
+            format!("{:.1?}", timing.uncached),
+            format!("{:.1?}", timing.cached),
+            speedup
+        )?;
+
+        if !timing.consistent {
+            write!(writer, "  (outputs differ)")?;
+        }
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{measure, samples};
+
+    #[test]
+    fn cached_and_uncached_runs_agree() {
+        for mut sample in samples() {
+            sample.input = vec![20];
+            let timing = measure(sample.name, &sample.program, &sample.input).unwrap();
+
+            assert!(timing.consistent, "{} produced different outputs", sample.name);
+            assert!(timing.instructions > 20);
+        }
+    }
+}
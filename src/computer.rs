@@ -1,24 +1,133 @@
-//! Computer implementation for problems 2 and 5.
-use std::io::{BufRead, Write};
+//! Computer implementation for problems 2, 5 and 9.
+use crate::devices::{Bus, Device};
+#[cfg(test)]
+use crate::extension::{Context, Extension, Flow};
+use crate::memory::Memory;
+use crate::profile::Profile;
+use crate::program::Program;
+use crate::snapshot::Snapshot;
+use crate::streams::{Input, Output};
+use crate::trace::{IoEvent, ResolvedOperand, TraceFilter, TraceRecord, Tracer};
+use crate::watch::{Access, WatchAction, WatchCause, WatchEvent, Watchpoint};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::hash_map::DefaultHasher;
+use std::convert::{TryFrom, TryInto};
+use std::fmt::{self, Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::ops::{Add, Mul};
+use std::str::FromStr;
+
+/// A numeric type usable as a single Intcode memory word, such as `i32`, `i64` or `i128`.
+pub trait Word: 'static + Send + Sync + Copy + Debug + Display + FromStr + PartialEq + PartialOrd
+    + Add<Output = Self> + Mul<Output = Self>
+    + From<i32> + Into<i128> + TryInto<i32> + TryInto<usize> {
+
+    fn to_address(self) -> Option<usize> {
+        TryInto::<usize>::try_into(self).ok()
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self>;
+
+    fn checked_mul(self, other: Self) -> Option<Self>;
+}
+
+macro_rules! impl_word {
+    ($($word:ty),*) => {
+        $(
+            impl Word for $word {
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$word>::checked_add(self, other)
+                }
+
+                fn checked_mul(self, other: Self) -> Option<Self> {
+                    <$word>::checked_mul(self, other)
+                }
+            }
+        )*
+    };
+}
+
+impl_word!(i32, i64, i128);
+
+#[derive(Debug)]
+pub enum ComputerError {
+    UnknownOpcode { address: usize, opcode: i128 },
+    InvalidMode { address: usize, mode: i128 },
+    OutOfBounds { address: i128, instruction: usize },
+    InputEof,
+    UnparsableInput(String),
+    Input(io::Error),
+    Output(io::Error),
+    Trace(io::Error),
+    StepLimit { limit: u64 },
+    InfiniteLoop { address: usize, step: u64 },
+    Overflow { instruction: usize }
+}
+
+impl Display for ComputerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ComputerError::UnknownOpcode { address, opcode } =>
+                write!(f, "Unexpected Operation Code: [{}] at address {}", opcode, address),
+            ComputerError::InvalidMode { address, mode } =>
+                write!(f, "Invalid parameter mode: [{}] at address {}", mode, address),
+            ComputerError::OutOfBounds { address, instruction } =>
+                write!(f, "Invalid memory address: [{}] accessed by instruction at address {}", address, instruction),
+            ComputerError::InputEof => write!(f, "Input ended before the program finished reading."),
+            ComputerError::UnparsableInput(input) => write!(f, "Unable to parse input: [{}]", input),
+            ComputerError::Input(error) => write!(f, "Unable to read input: {}", error),
+            ComputerError::Output(error) => write!(f, "Unable to write output: {}", error),
+            ComputerError::Trace(error) => write!(f, "Unable to write trace: {}", error),
+            ComputerError::StepLimit { limit } => write!(f, "Step limit of {} instructions reached.", limit),
+            ComputerError::InfiniteLoop { address, step } =>
+                write!(f, "Machine state repeated at address {} after {} steps.", address, step),
+            ComputerError::Overflow { instruction } =>
+                write!(f, "Arithmetic overflow in instruction at address {}", instruction)
+        }
+    }
+}
+
+impl std::error::Error for ComputerError {}
 
 #[derive(Debug)]
-enum ParameterMode {
+pub enum DecodeError {
+    Opcode(i128),
+    Mode(i128)
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum ParameterMode {
     Position,
-    Immediate
+    Immediate,
+    Relative
+}
+
+impl ParameterMode {
+    pub fn code(&self) -> i32 {
+        match self {
+            ParameterMode::Position => 0,
+            ParameterMode::Immediate => 1,
+            ParameterMode::Relative => 2
+        }
+    }
 }
 
-impl From<i32> for ParameterMode {
-    fn from(value: i32) -> Self {
+impl TryFrom<i32> for ParameterMode {
+    type Error = DecodeError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
         match value {
-            0 => ParameterMode::Position,
-            1 => ParameterMode::Immediate,
-            _ => panic!("Invalid parameter mode.")
+            0 => Ok(ParameterMode::Position),
+            1 => Ok(ParameterMode::Immediate),
+            2 => Ok(ParameterMode::Relative),
+            _ => Err(DecodeError::Mode(value.into()))
         }
     }
 }
 
-#[derive(Debug)]
-enum OpCode {
+#[derive(Debug, Copy, Clone)]
+pub enum OpCode {
     Addition,
     Multiplication,
     Write,
@@ -27,11 +136,21 @@ enum OpCode {
     JumpIfTrue,
     JumpIfFalse,
     LessThan,
-    Equals
+    Equals,
+    AdjustRelativeBase,
+    /// An instruction provided by an `Extension` registered with the computer.
+    #[cfg(test)]
+    Extension {
+        code: i32,
+        mnemonic: &'static str,
+        parameters: usize,
+        size: usize,
+        storage: Option<usize>
+    }
 }
 
 impl OpCode {
-    fn instruction_size(&self) -> usize {
+    pub fn instruction_size(&self) -> usize {
         match self {
             OpCode::Addition => 4,
             OpCode::Multiplication => 4,
@@ -41,238 +160,1125 @@ impl OpCode {
             OpCode::JumpIfTrue => 3,
             OpCode::JumpIfFalse => 3,
             OpCode::LessThan => 4,
-            OpCode::Equals => 4
+            OpCode::Equals => 4,
+            OpCode::AdjustRelativeBase => 2,
+            #[cfg(test)]
+            OpCode::Extension { size, .. } => *size
+        }
+    }
+
+    pub fn parameter_count(&self) -> usize {
+        match self {
+            #[cfg(test)]
+            OpCode::Extension { parameters, .. } => *parameters,
+            _ => self.instruction_size() - 1
+        }
+    }
+
+    // Index of the parameter naming the address an instruction stores to.
+    pub fn storage_parameter(&self) -> Option<usize> {
+        match self {
+            OpCode::Addition | OpCode::Multiplication | OpCode::LessThan | OpCode::Equals => Some(2),
+            OpCode::Write => Some(0),
+            #[cfg(test)]
+            OpCode::Extension { storage, .. } => *storage,
+            _ => None
+        }
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            OpCode::Addition => 1,
+            OpCode::Multiplication => 2,
+            OpCode::Write => 3,
+            OpCode::Output => 4,
+            OpCode::JumpIfTrue => 5,
+            OpCode::JumpIfFalse => 6,
+            OpCode::LessThan => 7,
+            OpCode::Equals => 8,
+            OpCode::AdjustRelativeBase => 9,
+            OpCode::Halt => 99,
+            #[cfg(test)]
+            OpCode::Extension { code, .. } => *code
         }
     }
 }
 
-impl From<i32> for OpCode {
-    fn from(opcode: i32) -> Self {
+impl TryFrom<i32> for OpCode {
+    type Error = DecodeError;
+
+    fn try_from(opcode: i32) -> Result<Self, Self::Error> {
         match opcode {
-            1 => OpCode::Addition,
-            2 => OpCode::Multiplication,
-            3 => OpCode::Write,
-            4 => OpCode::Output,
-            5 => OpCode::JumpIfTrue,
-            6 => OpCode::JumpIfFalse,
-            7 => OpCode::LessThan,
-            8 => OpCode::Equals,
-            99 => OpCode::Halt,
-            _ => panic!("Unexpected Operation Code: [{}]", opcode)
+            1 => Ok(OpCode::Addition),
+            2 => Ok(OpCode::Multiplication),
+            3 => Ok(OpCode::Write),
+            4 => Ok(OpCode::Output),
+            5 => Ok(OpCode::JumpIfTrue),
+            6 => Ok(OpCode::JumpIfFalse),
+            7 => Ok(OpCode::LessThan),
+            8 => Ok(OpCode::Equals),
+            9 => Ok(OpCode::AdjustRelativeBase),
+            99 => Ok(OpCode::Halt),
+            _ => Err(DecodeError::Opcode(opcode.into()))
         }
     }
 }
 
-#[derive(Debug)]
-struct Operation {
-    parameter_one_mode: ParameterMode,
-    parameter_two_mode: ParameterMode,
-    parameter_three_mode: ParameterMode,
-    opcode: OpCode
+#[derive(Debug, Copy, Clone)]
+pub struct Operation {
+    pub parameter_one_mode: ParameterMode,
+    pub parameter_two_mode: ParameterMode,
+    pub parameter_three_mode: ParameterMode,
+    pub opcode: OpCode
 }
 
-impl From<i32> for Operation {
-    fn from(instruction: i32) -> Self {
-        let mut numeric_state = instruction;
+impl Operation {
+    pub fn decode<W: Word>(instruction: W) -> Result<Self, DecodeError> {
+        Operation::decode_with(instruction, |_| None)
+    }
+
+    // Decodes with `extension` supplying any opcode that isn't built in.
+    fn decode_with<W: Word>(instruction: W, extension: impl Fn(i32) -> Option<OpCode>) -> Result<Self, DecodeError> {
+        let mut numeric_state: i32 = match instruction.try_into() {
+            Ok(value) => value,
+            Err(_) => return Err(DecodeError::Opcode(instruction.into()))
+        };
         let mut raw_parameter_mode;
 
         raw_parameter_mode = numeric_state / 10000;
-        let parameter_three_mode = ParameterMode::from(raw_parameter_mode);
+        let parameter_three_mode = ParameterMode::try_from(raw_parameter_mode)?;
         numeric_state -= raw_parameter_mode * 10000;
 
         raw_parameter_mode = numeric_state / 1000;
-        let parameter_two_mode = ParameterMode::from(raw_parameter_mode);
+        let parameter_two_mode = ParameterMode::try_from(raw_parameter_mode)?;
         numeric_state -= raw_parameter_mode * 1000;
 
         raw_parameter_mode = numeric_state / 100;
-        let parameter_one_mode = ParameterMode::from(raw_parameter_mode);
+        let parameter_one_mode = ParameterMode::try_from(raw_parameter_mode)?;
         numeric_state -= raw_parameter_mode * 100;
 
-        Operation {
+        Ok(Operation {
             parameter_one_mode,
             parameter_two_mode,
             parameter_three_mode,
-            opcode: OpCode::from(numeric_state)
+            opcode: OpCode::try_from(numeric_state).or_else(|error| extension(numeric_state).ok_or(error))?
+        })
+    }
+
+    pub fn parameter_count(&self) -> usize {
+        self.opcode.parameter_count()
+    }
+
+    pub fn parameter_mode(&self, index: usize) -> &ParameterMode {
+        match index {
+            0 => &self.parameter_one_mode,
+            1 => &self.parameter_two_mode,
+            _ => &self.parameter_three_mode
         }
     }
 }
 
-pub struct Computer<'a, StandardInput: BufRead, StandardOutput: Write> {
+// A parameter as fetched with its instruction, before the relative base or any data is read.
+#[derive(Debug, Copy, Clone)]
+enum Operand<W: Word> {
+    Immediate(W),
+    Position(usize),
+    Relative(W),
+    // A position parameter that can't be an address, reported if the instruction uses it.
+    Invalid(W)
+}
+
+// An operation together with its operands, as kept in the decode cache.
+#[derive(Debug, Copy, Clone)]
+struct Decoded<W: Word> {
+    operation: Operation,
+    operands: [Operand<W>; 3]
+}
+
+// Decoded instructions by address within the image, filled in as they first run.
+struct DecodeCache<W: Word> {
+    // One more than the index in `entries` of the instruction at each address, or zero.
+    // Allocated on the first insert, so machines that never run don't pay for it.
+    slots: Vec<u32>,
+    entries: Vec<Option<Decoded<W>>>
+}
+
+impl<W: Word> DecodeCache<W> {
+    fn new() -> Self {
+        DecodeCache { slots: vec![], entries: vec![] }
+    }
+
+    fn get(&self, address: usize) -> Option<&Decoded<W>> {
+        let slot = *self.slots.get(address)? as usize;
+        self.entries.get(slot.checked_sub(1)?)?.as_ref()
+    }
+
+    // An address keeps its entry once given one, so rewritten code doesn't grow the cache.
+    fn insert(&mut self, address: usize, image_len: usize, decoded: Decoded<W>) {
+        if address >= image_len {
+            return;
+        }
+
+        if self.slots.is_empty() {
+            self.slots = vec![0; image_len];
+        }
+
+        match self.slots[address] as usize {
+            0 => {
+                self.entries.push(Some(decoded));
+                self.slots[address] = self.entries.len() as u32;
+            },
+            slot => self.entries[slot - 1] = Some(decoded)
+        }
+    }
+
+    // Drops every entry whose words include `address`.
+    fn invalidate(&mut self, address: usize, longest_instruction: usize) {
+        for start in address.saturating_sub(longest_instruction - 1)..=address {
+            if let Some(slot) = self.slots.get(start).map(|slot| *slot as usize).filter(|slot| *slot > 0) {
+                let entry = &mut self.entries[slot - 1];
+                if matches!(entry, Some(decoded) if start + decoded.operation.opcode.instruction_size() > address) {
+                    *entry = None;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Termination {
+    Halted,
+    RanOffEnd
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum State<W: Word> {
+    Running,
+    NeedsInput,
+    Output(W),
+    /// A watchpoint asked to stop after the last instruction.
+    Paused,
+    Terminated(Termination)
+}
+
+struct Tracing<W: Word> {
+    tracer: Box<dyn Tracer<W> + Send>,
+    filter: TraceFilter,
+    step: u64,
+    writes: Option<Vec<(usize, W)>>
+}
+
+struct Watching<W: Word> {
+    watchpoints: Vec<Watchpoint>,
+    self_modification: Option<WatchAction>,
+    executed: HashSet<usize>,
+    events: Vec<WatchEvent<W>>,
+    paused: bool
+}
+
+impl<W: Word> Watching<W> {
+    fn new() -> Self {
+        Watching {
+            watchpoints: vec![],
+            self_modification: None,
+            executed: HashSet::new(),
+            events: vec![],
+            paused: false
+        }
+    }
+}
+
+// One word's share of the memory hash. Zero words add nothing, so memory hashes the same however
+// far it has grown.
+fn word_hash<W: Word>(address: usize, value: W) -> u64 {
+    if value == W::from(0) {
+        return 0;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    (address, Into::<i128>::into(value)).hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(PartialEq)]
+struct SeenState<W: Word> {
+    address: usize,
+    relative_base: W,
+    pending_input: usize,
+    // Length of the write log when the state was seen.
+    writes: usize
+}
+
+// States seen since the last input or output. Each records its place in a log of the writes made
+// since, so a state whose hash matches can be compared in full by undoing the later writes.
+struct LoopDetection<W: Word> {
+    // Sum of `word_hash` over memory, kept up to date on every write.
+    memory_hash: u64,
+    writes: Vec<(usize, W)>,
+    seen: HashMap<u64, Vec<SeenState<W>>>
+}
+
+impl<W: Word> LoopDetection<W> {
+    fn new(memory: &Memory<W>) -> Self {
+        let memory_hash = memory.image().iter().copied().enumerate()
+            .chain(memory.extension())
+            .fold(0u64, |hash, (address, value)| hash.wrapping_add(word_hash(address, value)));
+
+        LoopDetection { memory_hash, writes: vec![], seen: HashMap::new() }
+    }
+
+    fn write(&mut self, address: usize, old: W, new: W) {
+        self.memory_hash = self.memory_hash.wrapping_sub(word_hash(address, old)).wrapping_add(word_hash(address, new));
+        self.writes.push((address, old));
+    }
+
+    fn forget(&mut self) {
+        self.writes.clear();
+        self.seen.clear();
+    }
+
+    // Whether memory holds what it did when the log was `mark` writes long.
+    fn unchanged_since(&self, mark: usize, memory: &Memory<W>) -> bool {
+        // Walking back, the last value kept for an address is the one its first later write replaced.
+        let mut then = HashMap::new();
+        for (address, old) in self.writes[mark..].iter().rev() {
+            then.insert(*address, *old);
+        }
+
+        then.iter().all(|(address, value)| memory.read(*address) == *value)
+    }
+
+    // Remembers the state, returning whether it had been seen before.
+    fn revisit(&mut self, address: usize, relative_base: W, pending_input: usize, memory: &Memory<W>) -> bool {
+        let mut hasher = DefaultHasher::new();
+        (address, Into::<i128>::into(relative_base), pending_input, self.memory_hash).hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let state = SeenState { address, relative_base, pending_input, writes: self.writes.len() };
+        let revisited = self.seen.get(&hash).is_some_and(|states| states.iter().any(|seen| {
+            (seen.address, seen.relative_base, seen.pending_input) == (address, relative_base, pending_input)
+                && self.unchanged_since(seen.writes, memory)
+        }));
+
+        if !revisited {
+            self.seen.entry(hash).or_default().push(state);
+        }
+        revisited
+    }
+}
+
+// The longest built-in instruction, an opcode and three parameters.
+const MAX_INSTRUCTION_SIZE: usize = 4;
+
+pub struct Computer<'a, W: Word, In: Input<W>, Out: Output<W>> {
     address: usize,
-    execution_complete: bool,
-    memory: &'a mut [i32],
-    stdin: StandardInput,
-    stdout: StandardOutput
+    relative_base: W,
+    termination: Option<Termination>,
+    memory: Memory<'a, W>,
+    input: VecDeque<W>,
+    // Usually at most one value, though an extension instruction may produce several.
+    output: VecDeque<W>,
+    tracing: Option<Tracing<W>>,
+    steps: u64,
+    step_limit: Option<u64>,
+    // When loop detection is on.
+    loops: Option<LoopDetection<W>>,
+    watching: Option<Watching<W>>,
+    profile: Option<Profile>,
+    // When caching is on.
+    decoded: Option<DecodeCache<W>>,
+    #[cfg(test)]
+    extensions: Vec<Box<dyn Extension<W> + Send>>,
+    // The longest instruction this computer can decode, including extensions.
+    longest_instruction: usize,
+    bus: Bus<W>,
+    source: In,
+    sink: Out
 }
 
-impl<'a, StandardInput: BufRead, StandardOutput: Write> Computer<'a, StandardInput, StandardOutput> {
+/// A computer which owns its memory, created from a shared `Program`.
+pub type Machine<W, In, Out> = Computer<'static, W, In, Out>;
 
-    pub fn new(memory: &'a mut [i32], stdin: StandardInput, stdout: StandardOutput) -> Computer<'a, StandardInput, StandardOutput> {
+impl<W: Word, In: Input<W>, Out: Output<W>> Machine<W, In, Out> {
+
+    pub fn from_program(program: &Program<W>, source: In, sink: Out) -> Self {
+        Computer::with_memory(Memory::shared(program.shared_image()), source, sink)
+    }
+
+    pub fn from_snapshot(snapshot: &Snapshot<W>, source: In, sink: Out) -> Self {
+        let mut memory = Memory::owned(snapshot.image.clone());
+        for (address, value) in &snapshot.memory {
+            memory.write(*address, *value);
+        }
+
+        let mut machine = Computer::with_memory(memory, source, sink);
+        machine.address = snapshot.address;
+        machine.relative_base = snapshot.relative_base;
+        machine.termination = snapshot.termination;
+        machine.input = snapshot.input.iter().copied().collect();
+        machine
+    }
+}
+
+impl<'a, W: Word, In: Input<W>, Out: Output<W>> Computer<'a, W, In, Out> {
+
+    pub fn new(memory: &'a mut [W], source: In, sink: Out) -> Computer<'a, W, In, Out> {
+        Computer::with_memory(Memory::new(memory), source, sink)
+    }
+
+    /// Picks up a run another implementation started, such as transpiled code falling back.
+    pub fn resume(memory: Memory<'a, W>, address: usize, relative_base: W, source: In, sink: Out) -> Computer<'a, W, In, Out> {
+        let mut computer = Computer::with_memory(memory, source, sink);
+        computer.address = address;
+        computer.relative_base = relative_base;
+        if !computer.memory.written(address) {
+            computer.termination = Some(Termination::RanOffEnd);
+        }
+        computer
+    }
+
+    fn with_memory(memory: Memory<'a, W>, source: In, sink: Out) -> Computer<'a, W, In, Out> {
         Computer {
             memory,
             address: 0,
-            execution_complete: false,
-            stdin,
-            stdout
+            relative_base: W::from(0),
+            termination: None,
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            tracing: None,
+            steps: 0,
+            step_limit: None,
+            loops: None,
+            watching: None,
+            profile: None,
+            decoded: Some(DecodeCache::new()),
+            #[cfg(test)]
+            extensions: vec![],
+            longest_instruction: MAX_INSTRUCTION_SIZE,
+            bus: Bus::new(),
+            source,
+            sink
         }
     }
 
+    // Falling through past the last word of the image ends the program, as does reaching a word that
+    // was never written. A jump may run code the program has written beyond the image.
     fn advance(&mut self, instruction_size: usize) {
+        let image_len = self.memory.image_len();
+        let fell_off = instruction_size > 0 && self.address < image_len && self.address + instruction_size >= image_len;
+
         self.address += instruction_size;
-        if self.address >= self.memory.len() {
-            self.execution_complete = true;
+        if self.termination.is_none() && (fell_off || !self.memory.written(self.address)) {
+            self.termination = Some(Termination::RanOffEnd);
         }
     }
 
-    fn get_current_operation(&self) -> Operation {
-        Operation::from(self.memory[self.address])
+    pub fn get_current_operation(&self) -> Result<Operation, ComputerError> {
+        let address = self.address;
+
+        #[cfg(test)]
+        let extension = |code: i32| self.extensions.iter()
+            .find(|extension| extension.opcode() == code)
+            .map(|extension| OpCode::Extension {
+                code,
+                mnemonic: extension.mnemonic(),
+                parameters: extension.parameter_count(),
+                size: extension.instruction_size(),
+                storage: extension.storage_parameter()
+            });
+        #[cfg(not(test))]
+        let extension = |_| None;
+
+        Operation::decode_with(self.memory.read(address), extension).map_err(|error| match error {
+            DecodeError::Opcode(opcode) => ComputerError::UnknownOpcode { address, opcode },
+            DecodeError::Mode(mode) => ComputerError::InvalidMode { address, mode }
+        })
     }
 
-    fn get_parameter(&self, address: usize, mode: &ParameterMode) -> i32 {
-        match mode {
-            ParameterMode::Position => self.memory[self.memory[address] as usize],
-            ParameterMode::Immediate => self.memory[address]
+    fn current_decoded(&mut self) -> Result<Decoded<W>, ComputerError> {
+        if let Some(decoded) = self.decoded.as_ref().and_then(|cache| cache.get(self.address)) {
+            return Ok(*decoded);
         }
+
+        let operation = self.get_current_operation()?;
+        let decoded = Decoded { operation, operands: self.fetch_operands(&operation)? };
+
+        if let Some(cache) = &mut self.decoded {
+            cache.insert(self.address, self.memory.image_len(), decoded);
+        }
+
+        Ok(decoded)
     }
 
-    fn process_addition(&mut self, operation: &Operation) -> usize {
-        let parameter_one = self.get_parameter(self.address + 1, &operation.parameter_one_mode);
-        let parameter_two = self.get_parameter(self.address + 2, &operation.parameter_two_mode);
-        let storage_register = self.get_parameter(self.address + 3, &ParameterMode::Immediate) as usize;
+    // Fails if the instruction runs past the last address.
+    fn fetch_operands(&self, operation: &Operation) -> Result<[Operand<W>; 3], ComputerError> {
+        let size = operation.opcode.instruction_size();
+        if self.address.checked_add(size).is_none() {
+            return Err(ComputerError::OutOfBounds { address: self.address as i128 + size as i128 - 1, instruction: self.address });
+        }
 
-        let result = parameter_one + parameter_two;
+        let mut operands = [Operand::Immediate(W::from(0)); 3];
 
-        self.memory[storage_register] = result;
+        for (index, operand) in operands.iter_mut().enumerate().take(operation.parameter_count()) {
+            let word = self.memory.read(self.address + 1 + index);
+            *operand = match operation.parameter_mode(index) {
+                ParameterMode::Position => word.to_address().map_or(Operand::Invalid(word), Operand::Position),
+                ParameterMode::Immediate => Operand::Immediate(word),
+                ParameterMode::Relative => Operand::Relative(word)
+            };
+        }
 
-        operation.opcode.instruction_size()
+        Ok(operands)
     }
 
-    fn process_multiplication(&mut self, operation: &Operation) -> usize {
-        let parameter_one = self.get_parameter(self.address + 1, &operation.parameter_one_mode);
-        let parameter_two = self.get_parameter(self.address + 2, &operation.parameter_two_mode);
-        let storage_register = self.get_parameter(self.address + 3, &ParameterMode::Immediate) as usize;
+    fn checked(&self, result: Option<W>) -> Result<W, ComputerError> {
+        result.ok_or(ComputerError::Overflow { instruction: self.address })
+    }
 
-        let result = parameter_one * parameter_two;
+    fn to_address(&self, value: W) -> Result<usize, ComputerError> {
+        value.to_address().ok_or_else(|| ComputerError::OutOfBounds {
+            address: value.into(),
+            instruction: self.address
+        })
+    }
 
-        self.memory[storage_register] = result;
+    fn get_parameter(&self, operand: Operand<W>) -> Result<W, ComputerError> {
+        match operand {
+            Operand::Immediate(value) => Ok(value),
+            _ => Ok(self.peek_data(self.get_storage_address(operand)?))
+        }
+    }
 
-        operation.opcode.instruction_size()
+    // An immediate mode store is treated like position mode, writing to the address its word names.
+    fn get_storage_address(&self, operand: Operand<W>) -> Result<usize, ComputerError> {
+        match operand {
+            Operand::Position(address) => Ok(address),
+            Operand::Relative(offset) => self.to_address(self.checked(self.relative_base.checked_add(offset))?),
+            Operand::Immediate(value) | Operand::Invalid(value) => self.to_address(value)
+        }
     }
 
-    fn process_halt(&mut self, operation: &Operation) -> usize {
-        self.execution_complete = true;
-        operation.opcode.instruction_size()
+    // Reads a parameter on behalf of the executing instruction, reporting data reads to watchpoints.
+    fn load_parameter(&mut self, operand: Operand<W>) -> Result<W, ComputerError> {
+        if let Operand::Immediate(value) = operand {
+            return Ok(value);
+        }
+
+        let target = self.get_storage_address(operand)?;
+        let value = self.read_data(target)?;
+
+        if self.watching.is_some() {
+            self.watch(target, Access::Read, value);
+        }
+
+        Ok(value)
     }
 
-    fn process_write(&mut self, operation: &Operation) -> usize {
-        let parameter_one = self.get_parameter(self.address + 1, &ParameterMode::Immediate) as usize;
+    // Instructions are always fetched from memory, but the words they read and write may belong to a device.
+    fn read_data(&mut self, address: usize) -> Result<W, ComputerError> {
+        match self.bus.read(address) {
+            Some(value) => {
+                self.forget_states();
+                value
+            },
+            None => Ok(self.memory.read(address))
+        }
+    }
 
-        let mut buffer = String::new();
-        self.stdin.read_line(&mut buffer).unwrap();
+    fn peek_data(&self, address: usize) -> W {
+        self.bus.peek(address).unwrap_or_else(|| self.memory.read(address))
+    }
 
-        let value: i32 = buffer.trim().parse().unwrap();
+    fn write_data(&mut self, address: usize, value: W) -> Result<(), ComputerError> {
+        if let Some(result) = self.bus.write(address, value) {
+            self.forget_states();
+            return result;
+        }
 
-        self.memory[parameter_one] = value;
+        if let Some(cache) = &mut self.decoded {
+            cache.invalidate(address, self.longest_instruction);
+        }
+        if let Some(loops) = &mut self.loops {
+            loops.write(address, self.memory.read(address), value);
+        }
+        self.memory.write(address, value);
+        Ok(())
+    }
 
-        operation.opcode.instruction_size()
+    // Devices are outside the machine state, so talking to one is treated like input and output.
+    fn forget_states(&mut self) {
+        if let Some(loops) = &mut self.loops {
+            loops.forget();
+        }
     }
 
-    fn process_output(&mut self, operation: &Operation) -> usize {
-        let parameter_one = self.get_parameter(self.address + 1, &ParameterMode::Immediate) as usize;
+    fn watch(&mut self, address: usize, access: Access, value: W) {
+        let instruction = self.address;
 
-        let value = self.memory[parameter_one];
-        let mut output = value.to_string();
-        output.push('\n');
-        self.stdout.write_all(output.as_bytes()).unwrap();
+        if let Some(watching) = &mut self.watching {
+            let mut hits: Vec<(WatchCause, WatchAction)> = watching.watchpoints.iter().enumerate()
+                .filter(|(_, watchpoint)| watchpoint.access == access && watchpoint.addresses.contains(&address))
+                .map(|(index, watchpoint)| (WatchCause::Watchpoint(index), watchpoint.action))
+                .collect();
 
-        operation.opcode.instruction_size()
+            if let (Access::Write, Some(action)) = (access, watching.self_modification) {
+                if watching.executed.contains(&address) {
+                    hits.push((WatchCause::SelfModification, action));
+                }
+            }
+
+            for (cause, action) in hits {
+                watching.paused |= action == WatchAction::Pause;
+                watching.events.push(WatchEvent { instruction, address, access, value, cause });
+            }
+        }
     }
 
-    fn process_jump_if_true(&mut self, operation: &Operation) -> usize {
-        let parameter_one = self.get_parameter(self.address + 1, &operation.parameter_one_mode);
-        let parameter_two = self.get_parameter(self.address + 2, &operation.parameter_two_mode);
+    fn store(&mut self, address: usize, value: W) -> Result<(), ComputerError> {
+        if self.watching.is_some() {
+            self.watch(address, Access::Write, value);
+        }
+
+        self.write_data(address, value)?;
 
-        if parameter_one != 0 {
-            self.address = parameter_two as usize;
-            return 0;
+        if let Some(Tracing { writes: Some(writes), .. }) = &mut self.tracing {
+            writes.push((address, value));
         }
 
-        operation.opcode.instruction_size()
+        Ok(())
     }
 
-    fn process_jump_if_false(&mut self, operation: &Operation) -> usize {
-        let parameter_one = self.get_parameter(self.address + 1, &operation.parameter_one_mode);
-        let parameter_two = self.get_parameter(self.address + 2, &operation.parameter_two_mode);
+    fn process_addition(&mut self, decoded: &Decoded<W>) -> Result<usize, ComputerError> {
+        let parameter_one = self.load_parameter(decoded.operands[0])?;
+        let parameter_two = self.load_parameter(decoded.operands[1])?;
+        let storage_register = self.get_storage_address(decoded.operands[2])?;
+
+        let result = self.checked(parameter_one.checked_add(parameter_two))?;
+
+        self.store(storage_register, result)?;
 
-        if parameter_one == 0 {
-            self.address = parameter_two as usize;
-            return 0;
+        Ok(decoded.operation.opcode.instruction_size())
+    }
+
+    fn process_multiplication(&mut self, decoded: &Decoded<W>) -> Result<usize, ComputerError> {
+        let parameter_one = self.load_parameter(decoded.operands[0])?;
+        let parameter_two = self.load_parameter(decoded.operands[1])?;
+        let storage_register = self.get_storage_address(decoded.operands[2])?;
+
+        let result = self.checked(parameter_one.checked_mul(parameter_two))?;
+
+        self.store(storage_register, result)?;
+
+        Ok(decoded.operation.opcode.instruction_size())
+    }
+
+    fn process_halt(&mut self, decoded: &Decoded<W>) -> Result<usize, ComputerError> {
+        self.termination = Some(Termination::Halted);
+        Ok(decoded.operation.opcode.instruction_size())
+    }
+
+    fn process_write(&mut self, decoded: &Decoded<W>) -> Result<usize, ComputerError> {
+        let parameter_one = self.get_storage_address(decoded.operands[0])?;
+
+        let value = self.input.pop_front().ok_or(ComputerError::InputEof)?;
+
+        self.store(parameter_one, value)?;
+
+        Ok(decoded.operation.opcode.instruction_size())
+    }
+
+    fn process_output(&mut self, decoded: &Decoded<W>) -> Result<usize, ComputerError> {
+        let value = self.load_parameter(decoded.operands[0])?;
+
+        self.output.push_back(value);
+
+        Ok(decoded.operation.opcode.instruction_size())
+    }
+
+    fn process_jump_if_true(&mut self, decoded: &Decoded<W>) -> Result<usize, ComputerError> {
+        let parameter_one = self.load_parameter(decoded.operands[0])?;
+        let parameter_two = self.load_parameter(decoded.operands[1])?;
+
+        if parameter_one != W::from(0) {
+            self.address = self.to_address(parameter_two)?;
+            return Ok(0);
+        }
+
+        Ok(decoded.operation.opcode.instruction_size())
+    }
+
+    fn process_jump_if_false(&mut self, decoded: &Decoded<W>) -> Result<usize, ComputerError> {
+        let parameter_one = self.load_parameter(decoded.operands[0])?;
+        let parameter_two = self.load_parameter(decoded.operands[1])?;
+
+        if parameter_one == W::from(0) {
+            self.address = self.to_address(parameter_two)?;
+            return Ok(0);
         }
 
-        operation.opcode.instruction_size()
+        Ok(decoded.operation.opcode.instruction_size())
     }
 
-    fn process_less_than(&mut self, operation: &Operation) -> usize {
-        let parameter_one = self.get_parameter(self.address + 1, &operation.parameter_one_mode);
-        let parameter_two = self.get_parameter(self.address + 2, &operation.parameter_two_mode);
-        let storage_register = self.get_parameter(self.address + 3, &ParameterMode::Immediate) as usize;
+    fn process_less_than(&mut self, decoded: &Decoded<W>) -> Result<usize, ComputerError> {
+        let parameter_one = self.load_parameter(decoded.operands[0])?;
+        let parameter_two = self.load_parameter(decoded.operands[1])?;
+        let storage_register = self.get_storage_address(decoded.operands[2])?;
 
-        self.memory[storage_register] = if parameter_one < parameter_two {
-            1
+        let result = if parameter_one < parameter_two {
+            W::from(1)
         } else {
-            0
+            W::from(0)
         };
 
-        operation.opcode.instruction_size()
+        self.store(storage_register, result)?;
+
+        Ok(decoded.operation.opcode.instruction_size())
     }
 
-    fn process_equals(&mut self, operation: &Operation) -> usize {
-        let parameter_one = self.get_parameter(self.address + 1, &operation.parameter_one_mode);
-        let parameter_two = self.get_parameter(self.address + 2, &operation.parameter_two_mode);
-        let storage_register = self.get_parameter(self.address + 3, &ParameterMode::Immediate) as usize;
+    fn process_equals(&mut self, decoded: &Decoded<W>) -> Result<usize, ComputerError> {
+        let parameter_one = self.load_parameter(decoded.operands[0])?;
+        let parameter_two = self.load_parameter(decoded.operands[1])?;
+        let storage_register = self.get_storage_address(decoded.operands[2])?;
 
-        self.memory[storage_register] = if parameter_one == parameter_two {
-            1
+        let result = if parameter_one == parameter_two {
+            W::from(1)
         } else {
-            0
+            W::from(0)
         };
 
-        operation.opcode.instruction_size()
+        self.store(storage_register, result)?;
+
+        Ok(decoded.operation.opcode.instruction_size())
     }
 
-    fn process_operation(&mut self, operation: &Operation) -> usize {
-        match operation.opcode {
-            OpCode::Addition => self.process_addition(operation),
-            OpCode::Multiplication => self.process_multiplication(operation),
-            OpCode::Write => self.process_write(operation),
-            OpCode::Output => self.process_output(operation),
-            OpCode::JumpIfTrue => self.process_jump_if_true(operation),
-            OpCode::JumpIfFalse => self.process_jump_if_false(operation),
-            OpCode::LessThan => self.process_less_than(operation),
-            OpCode::Equals => self.process_equals(operation),
-            OpCode::Halt => self.process_halt(operation),
+    fn process_adjust_relative_base(&mut self, decoded: &Decoded<W>) -> Result<usize, ComputerError> {
+        let parameter_one = self.load_parameter(decoded.operands[0])?;
+
+        self.relative_base = self.checked(self.relative_base.checked_add(parameter_one))?;
+
+        Ok(decoded.operation.opcode.instruction_size())
+    }
+
+    #[cfg(test)]
+    fn process_extension(&mut self, decoded: &Decoded<W>) -> Result<usize, ComputerError> {
+        let code = decoded.operation.opcode.code();
+
+        // Taken out while the handler runs, since it borrows the rest of the computer.
+        let mut extensions = std::mem::take(&mut self.extensions);
+        let flow = match extensions.iter_mut().find(|extension| extension.opcode() == code) {
+            Some(extension) => extension.execute(&mut Executing { computer: self, decoded }),
+            None => Err(ComputerError::UnknownOpcode { address: self.address, opcode: code.into() })
+        };
+        self.extensions = extensions;
+
+        match flow? {
+            Flow::Next => Ok(decoded.operation.opcode.instruction_size()),
+            Flow::Jump(target) => {
+                self.address = target;
+                Ok(0)
+            },
+            Flow::Halt => {
+                self.termination = Some(Termination::Halted);
+                Ok(decoded.operation.opcode.instruction_size())
+            }
         }
     }
 
-    pub fn compute(&mut self) {
-        loop {
-            if self.execution_complete {
-                break;
+    fn process_operation(&mut self, decoded: &Decoded<W>) -> Result<usize, ComputerError> {
+        match decoded.operation.opcode {
+            OpCode::Addition => self.process_addition(decoded),
+            OpCode::Multiplication => self.process_multiplication(decoded),
+            OpCode::Write => self.process_write(decoded),
+            OpCode::Output => self.process_output(decoded),
+            OpCode::JumpIfTrue => self.process_jump_if_true(decoded),
+            OpCode::JumpIfFalse => self.process_jump_if_false(decoded),
+            OpCode::LessThan => self.process_less_than(decoded),
+            OpCode::Equals => self.process_equals(decoded),
+            OpCode::AdjustRelativeBase => self.process_adjust_relative_base(decoded),
+            OpCode::Halt => self.process_halt(decoded),
+            #[cfg(test)]
+            OpCode::Extension { .. } => self.process_extension(decoded)
+        }
+    }
+
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    pub fn relative_base(&self) -> W {
+        self.relative_base
+    }
+
+    pub fn termination(&self) -> Option<Termination> {
+        self.termination
+    }
+
+    pub fn pending_input(&self) -> &VecDeque<W> {
+        &self.input
+    }
+
+    /// Reads mapped addresses from their device, without side effects such as drawing a random number.
+    pub fn read_memory(&self, address: usize) -> W {
+        self.peek_data(address)
+    }
+
+    pub fn write_memory(&mut self, address: usize, value: W) -> Result<(), ComputerError> {
+        self.write_data(address, value)
+    }
+
+    /// Maps a device over `start..start + device.len()`, hiding the memory underneath.
+    ///
+    /// Panics if the range overlaps a device already attached. Devices are not part of a snapshot.
+    pub fn attach_device(&mut self, start: usize, device: Box<dyn Device<W> + Send>) {
+        self.bus.attach(start, device);
+    }
+
+    // Tracers and the input and output streams are not part of the machine state.
+    pub fn snapshot(&self) -> Snapshot<W> {
+        Snapshot {
+            address: self.address,
+            relative_base: self.relative_base,
+            termination: self.termination,
+            input: self.input.iter().copied().collect(),
+            image: self.memory.image().to_vec(),
+            memory: self.memory.extension()
+        }
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Fails with `StepLimit` instead of executing more than `limit` instructions in total.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /// Fails with `InfiniteLoop` when the machine returns to an exact earlier state without
+    /// reading input or writing output in between. Keeps every write until the next input or output.
+    pub fn detect_loops(&mut self, enabled: bool) {
+        self.loops = if enabled { Some(LoopDetection::new(&self.memory)) } else { None };
+    }
+
+    fn check_watchdog(&mut self, operation: &Operation) -> Result<(), ComputerError> {
+        if let Some(limit) = self.step_limit {
+            if self.steps >= limit {
+                return Err(ComputerError::StepLimit { limit });
+            }
+        }
+
+        if let Some(loops) = &mut self.loops {
+            // Input and output make earlier states unreliable, as the outside world may have changed.
+            if let OpCode::Write | OpCode::Output = operation.opcode {
+                loops.forget();
+            } else if loops.revisit(self.address, self.relative_base, self.input.len(), &self.memory) {
+                return Err(ComputerError::InfiniteLoop { address: self.address, step: self.steps });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watching.get_or_insert_with(Watching::new).watchpoints.push(watchpoint);
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        if let Some(watching) = &mut self.watching {
+            watching.watchpoints.clear();
+        }
+    }
+
+    /// Reports writes to any address that has been executed as part of an instruction since this was enabled.
+    pub fn detect_self_modification(&mut self, action: Option<WatchAction>) {
+        let watching = self.watching.get_or_insert_with(Watching::new);
+        watching.self_modification = action;
+        watching.executed.clear();
+    }
+
+    /// Logged events accumulate until taken, along with the ones that paused the machine.
+    pub fn take_watch_events(&mut self) -> Vec<WatchEvent<W>> {
+        match &mut self.watching {
+            Some(watching) => std::mem::take(&mut watching.events),
+            None => vec![]
+        }
+    }
+
+    /// Decoded instructions are cached by default; turning the cache off decodes on every step.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decoded = if enabled { self.decoded.take().or_else(|| Some(DecodeCache::new())) } else { None };
+    }
+
+    /// Teaches the computer an extra instruction. Extensions are not part of a snapshot.
+    ///
+    /// Panics if the opcode is built in, already registered, or not below 100, or if the
+    /// extension takes more than three parameters or is too short to hold them.
+    #[cfg(test)]
+    pub fn add_extension(&mut self, extension: Box<dyn Extension<W> + Send>) {
+        let code = extension.opcode();
+        let (parameters, size) = (extension.parameter_count(), extension.instruction_size());
+
+        assert!((0..100).contains(&code), "Extension opcode {} must be below 100.", code);
+        assert!(OpCode::try_from(code).is_err(), "Opcode {} is built in.", code);
+        assert!(self.extensions.iter().all(|other| other.opcode() != code), "Opcode {} is already registered.", code);
+        assert!(parameters <= 3 && size > parameters, "Opcode {} takes {} parameters in {} words.", code, parameters, size);
+
+        self.longest_instruction = self.longest_instruction.max(size);
+        self.extensions.push(extension);
+    }
+
+    pub fn enable_profiling(&mut self) {
+        self.profile.get_or_insert_with(Profile::new);
+    }
+
+    /// Returns the profile collected so far and stops profiling.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer<W> + Send>, filter: TraceFilter) {
+        self.tracing = Some(Tracing { tracer, filter, step: 0, writes: None });
+    }
+
+    // Resolves operands before execution when the current instruction passes the trace filter.
+    // Operands that can't be resolved are left off; executing the instruction then reports why.
+    fn begin_trace(&mut self, decoded: &Decoded<W>) -> Option<TraceRecord<W>> {
+        let operation = &decoded.operation;
+        let (step, matches) = match &self.tracing {
+            Some(tracing) => (tracing.step, tracing.filter.matches(self.address, &operation.opcode)),
+            None => return None
+        };
+
+        if !matches {
+            return None;
+        }
+
+        let mut operands = vec![];
+        for (index, operand) in decoded.operands.iter().enumerate().take(operation.parameter_count()) {
+            let operand = if operation.opcode.storage_parameter() == Some(index) {
+                self.get_storage_address(*operand).map(ResolvedOperand::Address)
+            } else {
+                self.get_parameter(*operand).map(ResolvedOperand::Value)
+            };
+
+            match operand {
+                Ok(operand) => operands.push(operand),
+                Err(_) => break
+            }
+        }
+
+        if let Some(tracing) = &mut self.tracing {
+            tracing.writes = Some(vec![]);
+        }
+
+        Some(TraceRecord {
+            step,
+            address: self.address,
+            instruction: self.memory.read(self.address),
+            operation: *operation,
+            operands,
+            writes: vec![],
+            io: None,
+            fault: None
+        })
+    }
+
+    // A faulted instruction is recorded but doesn't count as a step, as it never completed.
+    fn finish_trace(&mut self, record: Option<TraceRecord<W>>, input: Option<W>, fault: Option<&ComputerError>) -> Result<(), ComputerError> {
+        let output = self.output.front().copied();
+
+        if let Some(tracing) = &mut self.tracing {
+            if fault.is_none() {
+                tracing.step += 1;
+            }
+
+            if let Some(mut record) = record {
+                record.fault = fault.map(ToString::to_string);
+                record.writes = tracing.writes.take().unwrap_or_default();
+                record.io = match (input, output) {
+                    (Some(value), _) => Some(IoEvent::Input(value)),
+                    (None, Some(value)) => Some(IoEvent::Output(value)),
+                    (None, None) => None
+                };
+
+                tracing.tracer.record(&record).map_err(ComputerError::Trace)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn provide_input(&mut self, value: W) {
+        self.input.push_back(value);
+    }
+
+    pub fn step(&mut self) -> Result<State<W>, ComputerError> {
+        // Output beyond the first value of the last instruction, then any pause it raised, are
+        // reported before anything else runs.
+        if let Some(value) = self.output.pop_front() {
+            return Ok(State::Output(value));
+        }
+
+        if let Some(watching) = &mut self.watching {
+            if watching.paused {
+                watching.paused = false;
+                return Ok(State::Paused);
+            }
+        }
+
+        if let Some(termination) = self.termination {
+            return Ok(State::Terminated(termination));
+        }
+
+        let decoded = self.current_decoded()?;
+        let operation = &decoded.operation;
+
+        if let OpCode::Write = operation.opcode {
+            if self.input.is_empty() {
+                return Ok(State::NeedsInput);
             }
+        }
 
-            let operation = self.get_current_operation();
+        self.check_watchdog(operation)?;
 
-            let advance_instruction_by = self.process_operation(&operation);
+        let record = self.begin_trace(&decoded);
+        let input = match (&record, &operation.opcode) {
+            (Some(_), OpCode::Write) => self.input.front().copied(),
+            _ => None
+        };
 
-            self.advance(advance_instruction_by);
+        if let Some(watching) = &mut self.watching {
+            if watching.self_modification.is_some() {
+                watching.executed.extend(self.address..self.address + operation.opcode.instruction_size());
+            }
+        }
+
+        let address = self.address;
+        let advance_instruction_by = match self.process_operation(&decoded) {
+            Ok(size) => size,
+            Err(error) => {
+                // The instruction's own error is the one to report, even if recording it fails too.
+                if self.tracing.is_some() {
+                    let _ = self.finish_trace(record, input, Some(&error));
+                }
+                return Err(error);
+            }
+        };
+
+        if self.tracing.is_some() {
+            self.finish_trace(record, input, None)?;
+        }
+
+        self.steps += 1;
+        self.advance(advance_instruction_by);
+
+        if let Some(profile) = &mut self.profile {
+            profile.record(address, &operation.opcode, self.address);
+        }
+
+        if let Some(value) = self.output.pop_front() {
+            return Ok(State::Output(value));
+        }
+
+        if let Some(watching) = &mut self.watching {
+            if watching.paused {
+                watching.paused = false;
+                return Ok(State::Paused);
+            }
+        }
+
+        match self.termination {
+            Some(termination) => Ok(State::Terminated(termination)),
+            None => Ok(State::Running)
+        }
+    }
+
+    pub fn run_until_io(&mut self) -> Result<State<W>, ComputerError> {
+        loop {
+            match self.step()? {
+                State::Running => continue,
+                state => return Ok(state)
+            }
         }
     }
+
+    pub fn compute(&mut self) -> Result<Termination, ComputerError> {
+        loop {
+            match self.run_until_io()? {
+                State::NeedsInput => {
+                    let value = self.source.read()?;
+                    self.provide_input(value);
+                },
+                State::Output(value) => self.sink.write(value)?,
+                State::Terminated(termination) => return Ok(termination),
+                State::Running | State::Paused => ()
+            }
+        }
+    }
+}
+
+// The view of a computer an extension gets while its instruction runs.
+#[cfg(test)]
+struct Executing<'c, 'a, W: Word, In: Input<W>, Out: Output<W>> {
+    computer: &'c mut Computer<'a, W, In, Out>,
+    decoded: &'c Decoded<W>
+}
+
+#[cfg(test)]
+impl<'c, 'a, W: Word, In: Input<W>, Out: Output<W>> Executing<'c, 'a, W, In, Out> {
+    fn operand(&self, index: usize) -> Operand<W> {
+        let count = self.decoded.operation.parameter_count();
+        assert!(index < count, "Parameter {} requested from an instruction with {}.", index, count);
+
+        self.decoded.operands[index]
+    }
+}
+
+#[cfg(test)]
+impl<'c, 'a, W: Word, In: Input<W>, Out: Output<W>> Context<W> for Executing<'c, 'a, W, In, Out> {
+    fn address(&self) -> usize {
+        self.computer.address
+    }
+
+    fn relative_base(&self) -> W {
+        self.computer.relative_base
+    }
+
+    fn set_relative_base(&mut self, value: W) {
+        self.computer.relative_base = value;
+    }
+
+    fn parameter(&mut self, index: usize) -> Result<W, ComputerError> {
+        self.computer.load_parameter(self.operand(index))
+    }
+
+    fn store(&mut self, index: usize, value: W) -> Result<(), ComputerError> {
+        let target = self.computer.get_storage_address(self.operand(index))?;
+        self.computer.store(target, value)
+    }
+
+    fn read_memory(&mut self, address: usize) -> Result<W, ComputerError> {
+        self.computer.read_data(address)
+    }
+
+    fn write_memory(&mut self, address: usize, value: W) -> Result<(), ComputerError> {
+        self.computer.store(address, value)
+    }
+
+    fn output(&mut self, value: W) {
+        self.computer.output.push_back(value);
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::VecDeque;
     use std::io::{BufReader};
+    use std::sync::mpsc;
+    use std::thread;
     use crate::computer;
+    use crate::program::Program;
+    use crate::streams::{Iter, TextInput, TextOutput};
 
     #[test]
     fn io_one() {
@@ -280,9 +1286,9 @@ mod tests {
         let mut writer: Vec<u8> = vec![];
         let mut data = [3,9,8,9,10,9,4,9,99,-1,8];
 
-        let mut computer = computer::Computer::new(&mut data, buf, &mut writer);
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
 
-        computer.compute();
+        computer.compute().unwrap();
 
         assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "1\n");
     }
@@ -293,9 +1299,9 @@ mod tests {
         let mut writer: Vec<u8> = vec![];
         let mut data = [3,9,7,9,10,9,4,9,99,-1,8];
 
-        let mut computer = computer::Computer::new(&mut data, buf, &mut writer);
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
 
-        computer.compute();
+        computer.compute().unwrap();
 
         assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "1\n");
     }
@@ -306,9 +1312,9 @@ mod tests {
         let mut writer: Vec<u8> = vec![];
         let mut data = [3,3,1108,-1,8,3,4,3,99];
 
-        let mut computer = computer::Computer::new(&mut data, buf, &mut writer);
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
 
-        computer.compute();
+        computer.compute().unwrap();
 
         assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "1\n");
     }
@@ -319,9 +1325,9 @@ mod tests {
         let mut writer: Vec<u8> = vec![];
         let mut data = [3,3,1107,-1,8,3,4,3,99];
 
-        let mut computer = computer::Computer::new(&mut data, buf, &mut writer);
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
 
-        computer.compute();
+        computer.compute().unwrap();
 
         assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "1\n");
     }
@@ -332,9 +1338,9 @@ mod tests {
         let mut writer: Vec<u8> = vec![];
         let mut data = [3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9];
 
-        let mut computer = computer::Computer::new(&mut data, buf, &mut writer);
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
 
-        computer.compute();
+        computer.compute().unwrap();
 
         assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "0\n");
     }
@@ -345,9 +1351,9 @@ mod tests {
         let mut writer: Vec<u8> = vec![];
         let mut data = [3,3,1105,-1,9,1101,0,0,12,4,12,99,1];
 
-        let mut computer = computer::Computer::new(&mut data, buf, &mut writer);
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
 
-        computer.compute();
+        computer.compute().unwrap();
 
         assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "1\n");
     }
@@ -360,10 +1366,426 @@ mod tests {
                         1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,
                         999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99];
 
-        let mut computer = computer::Computer::new(&mut data, buf, &mut writer);
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
 
-        computer.compute();
+        computer.compute().unwrap();
 
         assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "1000\n");
     }
+
+    #[test]
+    fn relative_quine() {
+        let buf = BufReader::new("".as_bytes());
+        let mut writer: Vec<u8> = vec![];
+        let program = [109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+        let mut data = program;
+
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
+
+        computer.compute().unwrap();
+
+        let expected: String = program.iter().map(|value| format!("{}\n", value)).collect();
+        assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), expected);
+    }
+
+    #[test]
+    fn relative_storage() {
+        let buf = BufReader::new("".as_bytes());
+        let mut writer: Vec<u8> = vec![];
+        let mut data = [109,10,21101,3,4,0,204,0,99,0,0];
+
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
+
+        computer.compute().unwrap();
+
+        assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "7\n");
+        assert_eq!(data[10], 7);
+    }
+
+    #[test]
+    fn relative_input() {
+        let buf = BufReader::new("42\n".as_bytes());
+        let mut writer: Vec<u8> = vec![];
+        let mut data = [109,7,203,1,204,1,99,0,0];
+
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
+
+        computer.compute().unwrap();
+
+        assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "42\n");
+    }
+
+    #[test]
+    fn large_multiplication() {
+        let buf = BufReader::new("".as_bytes());
+        let mut writer: Vec<u8> = vec![];
+        let mut data: [i64; 8] = [1102,34915192,34915192,7,4,7,99,0];
+
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
+
+        computer.compute().unwrap();
+
+        assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "1219070632396864\n");
+    }
+
+    #[test]
+    fn large_immediate() {
+        let buf = BufReader::new("".as_bytes());
+        let mut writer: Vec<u8> = vec![];
+        let mut data: [i64; 3] = [104,1125899906842624,99];
+
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
+
+        computer.compute().unwrap();
+
+        assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "1125899906842624\n");
+    }
+
+    #[test]
+    fn wide_input() {
+        let buf = BufReader::new("1000000000000000000\n".as_bytes());
+        let mut writer: Vec<u8> = vec![];
+        let mut data: [i128; 9] = [3,0,1002,0,1000000,0,4,0,99];
+
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
+
+        computer.compute().unwrap();
+
+        assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "1000000000000000000000000\n");
+    }
+
+    #[test]
+    fn write_past_image() {
+        let buf = BufReader::new("".as_bytes());
+        let mut writer: Vec<u8> = vec![];
+        let mut data = [1101,2,3,1000,4,1000,4,2000,1101,0,5,1000000000,4,1000000000,99];
+
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
+
+        assert_eq!(computer.compute().unwrap(), computer::Termination::Halted);
+        assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "5\n0\n5\n");
+    }
+
+    #[test]
+    fn ran_off_end() {
+        let buf = BufReader::new("".as_bytes());
+        let mut writer: Vec<u8> = vec![];
+        let mut data = [1101,2,3,0];
+
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), TextOutput::new(&mut writer));
+
+        assert_eq!(computer.compute().unwrap(), computer::Termination::RanOffEnd);
+        assert_eq!(data[0], 5);
+    }
+
+    #[test]
+    fn runs_code_written_past_image() {
+        // Writes HLT at 1000 and jumps to it.
+        let mut data = [1101,99,0,1000,1105,1,1000];
+        let mut computer = computer::Computer::new(&mut data, VecDeque::new(), Vec::new());
+
+        assert_eq!(computer.compute().unwrap(), computer::Termination::Halted);
+
+        // Writes OUT #7 at 1000 and jumps to it, then falls through to a word that was never written.
+        let mut data = [1101,104,0,1000,1101,7,0,1001,1105,1,1000];
+        let mut outputs = vec![];
+        let mut computer = computer::Computer::new(&mut data, VecDeque::new(), &mut outputs);
+
+        assert_eq!(computer.compute().unwrap(), computer::Termination::RanOffEnd);
+        drop(computer);
+        assert_eq!(outputs, vec![7]);
+    }
+
+    #[test]
+    fn run_until_io() {
+        let mut data = [3,9,8,9,10,9,4,9,99,-1,8];
+
+        let mut computer = computer::Computer::new(&mut data, VecDeque::new(), Vec::new());
+
+        assert_eq!(computer.run_until_io().unwrap(), computer::State::NeedsInput);
+        assert_eq!(computer.run_until_io().unwrap(), computer::State::NeedsInput);
+
+        computer.provide_input(8);
+
+        assert_eq!(computer.run_until_io().unwrap(), computer::State::Output(1));
+        assert_eq!(computer.run_until_io().unwrap(), computer::State::Terminated(computer::Termination::Halted));
+        assert_eq!(computer.run_until_io().unwrap(), computer::State::Terminated(computer::Termination::Halted));
+    }
+
+    #[test]
+    fn step() {
+        let mut data = [1101,2,3,7,4,7,99,0];
+
+        let mut computer = computer::Computer::new(&mut data, VecDeque::new(), Vec::new());
+
+        assert_eq!(computer.step().unwrap(), computer::State::Running);
+        assert_eq!(computer.step().unwrap(), computer::State::Output(5));
+        assert_eq!(computer.step().unwrap(), computer::State::Terminated(computer::Termination::Halted));
+    }
+
+    #[test]
+    fn queued_inputs() {
+        let mut data = [3,11,3,12,1,11,12,13,4,13,99,0,0,0];
+
+        let mut computer = computer::Computer::new(&mut data, VecDeque::new(), Vec::new());
+
+        computer.provide_input(20);
+        computer.provide_input(22);
+
+        assert_eq!(computer.run_until_io().unwrap(), computer::State::Output(42));
+        assert_eq!(computer.run_until_io().unwrap(), computer::State::Terminated(computer::Termination::Halted));
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        let mut data = [1101,2,3,5,42,0];
+
+        let mut computer = computer::Computer::new(&mut data, VecDeque::new(), Vec::new());
+
+        match computer.compute() {
+            Err(computer::ComputerError::UnknownOpcode { address: 4, opcode: 42 }) => (),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn invalid_mode() {
+        let mut data = [1101,2,3,5,304,0,99];
+
+        let mut computer = computer::Computer::new(&mut data, VecDeque::new(), Vec::new());
+
+        match computer.compute() {
+            Err(computer::ComputerError::InvalidMode { address: 4, mode: 3 }) => (),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let mut data = [1101,2,3,7,4,-7,99,0];
+
+        let mut computer = computer::Computer::new(&mut data, VecDeque::new(), Vec::new());
+
+        match computer.compute() {
+            Err(computer::ComputerError::OutOfBounds { address: -7, instruction: 4 }) => (),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn instruction_past_the_last_address() {
+        let mut memory = crate::memory::Memory::owned(vec![99]);
+        memory.write(usize::MAX - 1, 1101);
+
+        let mut computer = computer::Computer::resume(memory, usize::MAX - 1, 0, VecDeque::new(), Vec::new());
+
+        match computer.step() {
+            Err(computer::ComputerError::OutOfBounds { address, instruction }) =>
+                assert_eq!((address, instruction), (usize::MAX as i128 + 2, usize::MAX - 1)),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        let programs: [(&[i64], usize); 4] = [
+            (&[1101,i64::MAX,1,0,99], 0),
+            (&[1102,i64::MAX,2,0,99], 0),
+            (&[109,i64::MAX,109,1,99], 2),
+            (&[109,i64::MAX,204,1,99], 2)
+        ];
+
+        for (program, address) in programs.iter() {
+            let mut data = program.to_vec();
+            let mut computer = computer::Computer::new(&mut data, VecDeque::new(), Vec::new());
+
+            match computer.compute() {
+                Err(computer::ComputerError::Overflow { instruction }) if instruction == *address => (),
+                result => panic!("Unexpected result: {:?}", result)
+            }
+        }
+    }
+
+    #[test]
+    fn input_eof() {
+        let mut data = [3,0,99];
+
+        let mut computer = computer::Computer::new(&mut data, VecDeque::new(), Vec::new());
+
+        match computer.compute() {
+            Err(computer::ComputerError::InputEof) => (),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn unparsable_input() {
+        let buf = BufReader::new("eight\n".as_bytes());
+        let mut data = [3,0,99];
+
+        let mut computer = computer::Computer::new(&mut data, TextInput::new(buf), Vec::new());
+
+        match computer.compute() {
+            Err(computer::ComputerError::UnparsableInput(ref input)) if input == "eight" => (),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn output_failure() {
+        let mut data = [104,1,99];
+        let mut buffer = [0u8; 1];
+
+        let mut computer = computer::Computer::new(&mut data, VecDeque::new(), TextOutput::new(&mut buffer[..]));
+
+        match computer.compute() {
+            Err(computer::ComputerError::Output(_)) => (),
+            result => panic!("Unexpected result: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn machine_from_program() {
+        let program: Program<i64> = "1,0,0,0,99".parse().unwrap();
+
+        let mut first = computer::Machine::from_program(&program, VecDeque::new(), Vec::new());
+        let mut second = computer::Machine::from_program(&program, VecDeque::new(), Vec::new());
+
+        first.write_memory(1, 4).unwrap();
+        first.compute().unwrap();
+        second.compute().unwrap();
+
+        assert_eq!(first.read_memory(0), 100);
+        assert_eq!(second.read_memory(0), 2);
+        assert_eq!(program.image(), &[1, 0, 0, 0, 99]);
+    }
+
+    #[test]
+    fn machines_across_threads() {
+        let program: Program<i64> = "3,9,8,9,10,9,4,9,99,-1,8".parse().unwrap();
+
+        let handles: Vec<_> = (7..10).map(|value| {
+            let mut machine = computer::Machine::from_program(&program, VecDeque::new(), Vec::new());
+            thread::spawn(move || {
+                machine.provide_input(value);
+                machine.run_until_io().unwrap()
+            })
+        }).collect();
+
+        let outputs: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+
+        assert_eq!(outputs, vec![computer::State::Output(0), computer::State::Output(1), computer::State::Output(0)]);
+    }
+
+    #[test]
+    fn value_io() {
+        let mut data = [3,9,8,9,10,9,4,9,99,-1,8];
+        let mut outputs = vec![];
+
+        let mut computer = computer::Computer::new(&mut data, Iter(vec![8].into_iter()), &mut outputs);
+
+        computer.compute().unwrap();
+
+        assert_eq!(outputs, vec![1]);
+    }
+
+    #[test]
+    fn channel_io() {
+        let program: Program<i64> = "3,0,4,0,3,0,4,0,99".parse().unwrap();
+        let (input_sender, input_receiver) = mpsc::channel();
+        let (output_sender, output_receiver) = mpsc::channel();
+
+        let mut machine = computer::Machine::from_program(&program, input_receiver, output_sender);
+        let handle = thread::spawn(move || machine.compute().unwrap());
+
+        input_sender.send(4).unwrap();
+        assert_eq!(output_receiver.recv().unwrap(), 4);
+        input_sender.send(5).unwrap();
+        assert_eq!(output_receiver.recv().unwrap(), 5);
+
+        assert_eq!(handle.join().unwrap(), computer::Termination::Halted);
+    }
+
+    #[test]
+    fn step_limit() {
+        let program: Program<i64> = "1105,1,0".parse().unwrap();
+        let mut machine = computer::Machine::from_program(&program, VecDeque::new(), Vec::new());
+        machine.set_step_limit(Some(10));
+
+        assert!(matches!(machine.compute(), Err(computer::ComputerError::StepLimit { limit: 10 })));
+        assert_eq!(machine.steps(), 10);
+
+        machine.set_step_limit(None);
+        assert!(matches!(machine.step(), Ok(computer::State::Running)));
+    }
+
+    #[test]
+    fn detects_loops() {
+        // Counts down from 3 before jumping to itself.
+        let program: Program<i64> = "1001,10,-1,10,1005,10,0,1105,1,7,3".parse().unwrap();
+        let mut machine = computer::Machine::from_program(&program, VecDeque::new(), Vec::new());
+        machine.detect_loops(true);
+
+        assert!(matches!(machine.compute(), Err(computer::ComputerError::InfiniteLoop { address: 7, step: 7 })));
+    }
+
+    #[test]
+    fn loop_detection_compares_full_states() {
+        let mut image = [1, 2, 3];
+        let mut memory = crate::memory::Memory::new(&mut image);
+        let mut loops = computer::LoopDetection::new(&memory);
+        let hash = loops.memory_hash;
+
+        assert!(!loops.revisit(0, 0, 0, &memory));
+
+        // The running hash matches one computed from scratch.
+        loops.write(1, 2, 5);
+        memory.write(1, 5);
+        let running = loops.memory_hash;
+        assert_eq!(running, computer::LoopDetection::new(&memory).memory_hash);
+
+        // Forced to collide with the first state, only the full comparison tells them apart.
+        loops.memory_hash = hash;
+        assert!(!loops.revisit(0, 0, 0, &memory));
+
+        loops.memory_hash = running;
+        loops.write(1, 5, 2);
+        memory.write(1, 2);
+        assert_eq!(loops.memory_hash, hash);
+        assert!(loops.revisit(0, 0, 0, &memory));
+    }
+
+    #[test]
+    fn io_resets_loop_detection() {
+        let program: Program<i64> = "3,9,4,9,1105,1,0".parse().unwrap();
+        let mut outputs = vec![];
+        let mut machine = computer::Machine::from_program(&program, Iter(vec![1, 1, 1].into_iter()), &mut outputs);
+        machine.detect_loops(true);
+
+        assert!(matches!(machine.compute(), Err(computer::ComputerError::InputEof)));
+        drop(machine);
+
+        assert_eq!(outputs, vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn decode_cache_sees_rewritten_instructions() {
+        // Adds into address 7, then jumps back to the addition.
+        let program: Program<i64> = "1101,2,3,7,1105,1,0,0".parse().unwrap();
+        let mut machine = computer::Machine::from_program(&program, VecDeque::new(), Vec::new());
+
+        machine.step().unwrap();
+        machine.step().unwrap();
+        assert_eq!(machine.read_memory(7), 5);
+
+        machine.write_memory(0, 1102).unwrap();
+        machine.step().unwrap();
+        assert_eq!(machine.read_memory(7), 6);
+
+        // Operands are cached with their instruction, so rewriting one is seen too.
+        machine.write_memory(3, 6).unwrap();
+        machine.step().unwrap();
+        machine.step().unwrap();
+        assert_eq!(machine.read_memory(6), 6);
+    }
 }
@@ -0,0 +1,251 @@
+//! Static control flow graphs of Intcode programs, recovered by decoding from the entry point.
+use crate::computer::{OpCode, ParameterMode, Word};
+use crate::disassembler::{decode_line, Line};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    FallThrough,
+    Jump
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// The block starting at this address.
+    Block(usize),
+    /// An immediate jump target past the end of the image.
+    Outside(usize),
+    /// A jump through memory, which can't be followed statically.
+    Unresolved
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: Target,
+    pub kind: EdgeKind
+}
+
+#[derive(Debug)]
+pub struct Block<W: Word> {
+    pub start: usize,
+    pub lines: Vec<Line<W>>
+}
+
+#[derive(Debug)]
+pub struct ControlFlowGraph<W: Word> {
+    /// Blocks keyed by their start address.
+    pub blocks: BTreeMap<usize, Block<W>>,
+    pub edges: Vec<Edge>
+}
+
+pub fn is_jump(line: &Line<impl Word>) -> bool {
+    matches!(line, Line::Instruction { operation, .. } if matches!(operation.opcode, OpCode::JumpIfTrue | OpCode::JumpIfFalse))
+}
+
+// Where execution can go after a line. Jumps with an immediate condition only get the edge they always take.
+pub fn successors<W: Word>(line: &Line<W>, image_len: usize) -> Vec<(EdgeKind, Target)> {
+    let (address, operation, operands) = match line {
+        Line::Instruction { address, operation, operands } => (*address, operation, operands),
+        Line::Data { .. } => return vec![]
+    };
+    let next = address + line.size();
+    let fall_through = if next < image_len { vec![(EdgeKind::FallThrough, Target::Block(next))] } else { vec![] };
+
+    let jumps_if_nonzero = match operation.opcode {
+        OpCode::Halt => return vec![],
+        OpCode::JumpIfTrue => true,
+        OpCode::JumpIfFalse => false,
+        _ => return fall_through
+    };
+
+    let taken = match operation.parameter_one_mode {
+        ParameterMode::Immediate => Some((operands[0] != W::from(0)) == jumps_if_nonzero),
+        _ => None
+    };
+    let target = match (&operation.parameter_two_mode, operands[1].to_address()) {
+        (ParameterMode::Immediate, Some(target)) if target < image_len => Target::Block(target),
+        (ParameterMode::Immediate, Some(target)) => Target::Outside(target),
+        _ => Target::Unresolved
+    };
+
+    let mut successors = vec![];
+    if taken != Some(false) {
+        successors.push((EdgeKind::Jump, target));
+    }
+    if taken != Some(true) {
+        successors.extend(fall_through);
+    }
+
+    successors
+}
+
+impl<W: Word> ControlFlowGraph<W> {
+
+    /// Follows every statically known path from address 0. Blocks start at jump targets, after
+    /// jumps and wherever paths through overlapping instructions meet.
+    pub fn build(image: &[W]) -> Self {
+        let mut lines: HashMap<usize, Line<W>> = HashMap::new();
+        let mut successor_map: HashMap<usize, Vec<(EdgeKind, Target)>> = HashMap::new();
+        let mut predecessors: HashMap<usize, usize> = HashMap::new();
+        let mut leaders: BTreeSet<usize> = BTreeSet::new();
+        let mut pending = vec![];
+
+        if !image.is_empty() {
+            leaders.insert(0);
+            pending.push(0);
+        }
+
+        while let Some(address) = pending.pop() {
+            if lines.contains_key(&address) {
+                continue;
+            }
+
+            let line = decode_line(|address| image[address], address, image.len());
+            let successors = successors(&line, image.len());
+
+            for (_, target) in &successors {
+                if let Target::Block(target) = target {
+                    *predecessors.entry(*target).or_insert(0) += 1;
+                    if is_jump(&line) {
+                        leaders.insert(*target);
+                    }
+                    pending.push(*target);
+                }
+            }
+
+            lines.insert(address, line);
+            successor_map.insert(address, successors);
+        }
+
+        leaders.extend(predecessors.iter().filter(|(_, count)| **count > 1).map(|(address, _)| *address));
+
+        let mut blocks = BTreeMap::new();
+        let mut edges = vec![];
+
+        for start in leaders.iter().copied() {
+            let mut block = Block { start, lines: vec![] };
+            let mut address = start;
+
+            loop {
+                let line = lines.remove(&address).expect("Every leader and fall through was decoded.");
+                let successors = &successor_map[&address];
+                block.lines.push(line);
+
+                match successors.as_slice() {
+                    [(EdgeKind::FallThrough, Target::Block(next))] if !leaders.contains(next) => address = *next,
+                    _ => {
+                        edges.extend(successors.iter().map(|(kind, to)| Edge { from: start, to: *to, kind: *kind }));
+                        break;
+                    }
+                }
+            }
+
+            blocks.insert(start, block);
+        }
+
+        ControlFlowGraph { blocks, edges }
+    }
+
+    /// Renders the graph in Graphviz DOT, with one node per block listing its instructions.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+
+        writeln!(dot, "digraph intcode {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        for block in self.blocks.values() {
+            let label: String = block.lines.iter().map(|line| format!("{}\\l", line)).collect();
+            writeln!(dot, "    b{} [label=\"{}\"];", block.start, label).unwrap();
+        }
+
+        for edge in &self.edges {
+            let to = match edge.to {
+                Target::Block(address) => format!("b{}", address),
+                Target::Outside(address) => {
+                    writeln!(dot, "    outside{} [label=\"{:04}\", shape=plaintext];", address, address).unwrap();
+                    format!("outside{}", address)
+                },
+                Target::Unresolved => {
+                    writeln!(dot, "    unresolved{} [label=\"?\", shape=diamond];", edge.from).unwrap();
+                    format!("unresolved{}", edge.from)
+                }
+            };
+            let style = match edge.kind {
+                EdgeKind::FallThrough => "",
+                EdgeKind::Jump => " [label=\"jump\"]"
+            };
+
+            writeln!(dot, "    b{} -> {}{};", edge.from, to, style).unwrap();
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ControlFlowGraph, Edge, EdgeKind, Target};
+
+    fn starts(graph: &ControlFlowGraph<i64>) -> Vec<(usize, usize)> {
+        graph.blocks.values().map(|block| (block.start, block.lines.len())).collect()
+    }
+
+    #[test]
+    fn straight_line() {
+        let graph = ControlFlowGraph::build(&[3,9,8,9,10,9,4,9,99,-1,8]);
+
+        assert_eq!(starts(&graph), vec![(0, 4)]);
+        assert!(graph.edges.is_empty());
+    }
+
+    #[test]
+    fn loops() {
+        // Counts memory location 9 down to zero.
+        let graph = ControlFlowGraph::build(&[1001,9,-1,9,1005,9,0,99,0,3]);
+
+        assert_eq!(starts(&graph), vec![(0, 2), (7, 1)]);
+        assert_eq!(graph.edges, vec![
+            Edge { from: 0, to: Target::Block(0), kind: EdgeKind::Jump },
+            Edge { from: 0, to: Target::Block(7), kind: EdgeKind::FallThrough }
+        ]);
+    }
+
+    #[test]
+    fn splits_at_jump_targets() {
+        // Jumps over an output into the middle of a straight run.
+        let graph = ControlFlowGraph::build(&[1106,0,5,104,1,104,2,99]);
+
+        assert_eq!(starts(&graph), vec![(0, 1), (5, 2)]);
+        assert_eq!(graph.edges, vec![Edge { from: 0, to: Target::Block(5), kind: EdgeKind::Jump }]);
+    }
+
+    #[test]
+    fn indirect_and_outside_jumps() {
+        let graph = ControlFlowGraph::build(&[3,8,1005,8,9,105,1,8,0]);
+
+        assert_eq!(starts(&graph), vec![(0, 2), (5, 1)]);
+        assert_eq!(graph.edges, vec![
+            Edge { from: 0, to: Target::Outside(9), kind: EdgeKind::Jump },
+            Edge { from: 0, to: Target::Block(5), kind: EdgeKind::FallThrough },
+            Edge { from: 5, to: Target::Unresolved, kind: EdgeKind::Jump }
+        ]);
+    }
+
+    #[test]
+    fn dot() {
+        let graph = ControlFlowGraph::build(&[1001,9,-1,9,1005,9,0,99,0,3]);
+
+        assert_eq!(graph.to_dot(), "\
+digraph intcode {
+    node [shape=box, fontname=\"monospace\"];
+    b0 [label=\"0000: ADD  [9], #-1, [9]\\l0004: JNZ  [9], #0\\l\"];
+    b7 [label=\"0007: HLT\\l\"];
+    b0 -> b0 [label=\"jump\"];
+    b0 -> b7;
+}
+");
+    }
+}
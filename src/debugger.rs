@@ -0,0 +1,406 @@
+//! Interactive debugger for Intcode programs.
+use crate::computer::{ComputerError, Machine, State, Termination, Word};
+use crate::disassembler::decode_line;
+use crate::program::Program;
+use crate::watch::{WatchAction, Watchpoint};
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+const HELP: &str = "\
+step [n]              execute n instructions (default 1)
+continue              run until a breakpoint, input is needed or the program ends
+break <addr>          set a breakpoint
+delete <addr>         remove a breakpoint
+breakpoints           list breakpoints
+watch <addr> [n]      pause when any of n words (default 1) is written
+rwatch <addr> [n]     pause when any of n words (default 1) is read
+unwatch               remove all watchpoints
+smc                   toggle pausing on writes to already executed code
+memory <addr> [n]     show n words of memory (default 1)
+set <addr> <value>    write a word to memory
+disassemble [addr] [n] show n instructions starting at addr (default: current instruction)
+input <values...>     queue input values
+output                show and clear collected output
+info                  show registers and queues
+quit                  leave the debugger
+";
+
+#[derive(Debug)]
+enum Stop {
+    Breakpoint(usize),
+    Watchpoint,
+    NeedsInput,
+    Terminated(Termination),
+    Failed(ComputerError)
+}
+
+pub struct Debugger<W: Word> {
+    machine: Machine<W, VecDeque<W>, Vec<W>>,
+    breakpoints: BTreeSet<usize>,
+    self_modification: bool,
+    outputs: VecDeque<W>
+}
+
+impl<W: Word> Debugger<W> {
+
+    pub fn new(program: &Program<W>) -> Self {
+        Debugger {
+            machine: Machine::from_program(program, VecDeque::new(), vec![]),
+            breakpoints: BTreeSet::new(),
+            self_modification: false,
+            outputs: VecDeque::new()
+        }
+    }
+
+    // Executes one instruction, reporting any output and whether execution has to stop.
+    fn execute_instruction<Wr: Write>(&mut self, out: &mut Wr) -> io::Result<Option<Stop>> {
+        let state = self.machine.step();
+
+        for event in self.machine.take_watch_events() {
+            writeln!(out, "Watch: {}", event)?;
+        }
+
+        match state {
+            Ok(State::Running) => Ok(None),
+            Ok(State::Paused) => Ok(Some(Stop::Watchpoint)),
+            Ok(State::Output(value)) => {
+                writeln!(out, "Output: {}", value)?;
+                self.outputs.push_back(value);
+                Ok(None)
+            },
+            Ok(State::NeedsInput) => Ok(Some(Stop::NeedsInput)),
+            Ok(State::Terminated(termination)) => Ok(Some(Stop::Terminated(termination))),
+            Err(error) => Ok(Some(Stop::Failed(error)))
+        }
+    }
+
+    fn report_stop<Wr: Write>(&self, stop: Stop, out: &mut Wr) -> io::Result<()> {
+        match stop {
+            Stop::Breakpoint(address) => writeln!(out, "Breakpoint at {:04}.", address)?,
+            Stop::Watchpoint => {
+                writeln!(out, "Paused by watchpoint.")?;
+                self.show_current(out)?;
+            },
+            Stop::NeedsInput => writeln!(out, "Waiting for input at {:04}.", self.machine.address())?,
+            Stop::Terminated(Termination::Halted) => writeln!(out, "Program halted.")?,
+            Stop::Terminated(Termination::RanOffEnd) => writeln!(out, "Program ran off the end of its image.")?,
+            Stop::Failed(error) => writeln!(out, "Error: {}", error)?
+        }
+
+        Ok(())
+    }
+
+    fn show_current<Wr: Write>(&self, out: &mut Wr) -> io::Result<()> {
+        if self.machine.termination().is_none() {
+            self.disassemble(self.machine.address(), 1, out)?;
+        }
+
+        Ok(())
+    }
+
+    fn step<Wr: Write>(&mut self, count: usize, out: &mut Wr) -> io::Result<()> {
+        for _ in 0..count {
+            if let Some(stop) = self.execute_instruction(out)? {
+                return self.report_stop(stop, out);
+            }
+        }
+
+        self.show_current(out)
+    }
+
+    fn resume<Wr: Write>(&mut self, out: &mut Wr) -> io::Result<()> {
+        loop {
+            if let Some(stop) = self.execute_instruction(out)? {
+                return self.report_stop(stop, out);
+            }
+
+            let address = self.machine.address();
+            if self.breakpoints.contains(&address) {
+                self.report_stop(Stop::Breakpoint(address), out)?;
+                return self.show_current(out);
+            }
+        }
+    }
+
+    fn add_watchpoint<Wr: Write>(&mut self, watchpoint: Watchpoint, out: &mut Wr) -> io::Result<()> {
+        writeln!(out, "Watching {:04}..{:04} for {:?}.", watchpoint.addresses.start, watchpoint.addresses.end, watchpoint.access)?;
+        self.machine.add_watchpoint(watchpoint);
+        Ok(())
+    }
+
+    fn disassemble<Wr: Write>(&self, address: usize, count: usize, out: &mut Wr) -> io::Result<()> {
+        let mut address = address;
+
+        for _ in 0..count {
+            let line = decode_line(|address| self.machine.read_memory(address), address, usize::MAX);
+            let marker = if address == self.machine.address() { "=>" } else { "  " };
+
+            writeln!(out, "{} {}", marker, line)?;
+            address = match address.checked_add(line.size()) {
+                Some(next) => next,
+                None => break
+            };
+        }
+
+        Ok(())
+    }
+
+    fn info<Wr: Write>(&self, out: &mut Wr) -> io::Result<()> {
+        let status = match self.machine.termination() {
+            Some(Termination::Halted) => "halted",
+            Some(Termination::RanOffEnd) => "ran off end",
+            None => "running"
+        };
+        let pending: Vec<String> = self.machine.pending_input().iter().map(W::to_string).collect();
+        let outputs: Vec<String> = self.outputs.iter().map(W::to_string).collect();
+
+        writeln!(out, "address: {}", self.machine.address())?;
+        writeln!(out, "relative base: {}", self.machine.relative_base())?;
+        writeln!(out, "steps: {}", self.machine.steps())?;
+        writeln!(out, "status: {}", status)?;
+        writeln!(out, "input queue: [{}]", pending.join(", "))?;
+        writeln!(out, "output queue: [{}]", outputs.join(", "))
+    }
+
+    // Returns false once the user asks to quit.
+    pub fn execute<Wr: Write>(&mut self, command: &str, out: &mut Wr) -> io::Result<bool> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let addresses: Vec<Option<usize>> = words.iter().skip(1).map(|word| word.parse().ok()).collect();
+
+        match (words.first().copied(), addresses.as_slice()) {
+            (None, _) => (),
+            (Some("step"), []) | (Some("s"), []) => self.step(1, out)?,
+            (Some("step"), [Some(count)]) | (Some("s"), [Some(count)]) => self.step(*count, out)?,
+            (Some("continue"), []) | (Some("c"), []) => self.resume(out)?,
+            (Some("break"), [Some(address)]) | (Some("b"), [Some(address)]) => {
+                self.breakpoints.insert(*address);
+                writeln!(out, "Breakpoint set at {:04}.", address)?;
+            },
+            (Some("delete"), [Some(address)]) => {
+                if self.breakpoints.remove(address) {
+                    writeln!(out, "Breakpoint removed from {:04}.", address)?;
+                } else {
+                    writeln!(out, "No breakpoint at {:04}.", address)?;
+                }
+            },
+            (Some("breakpoints"), []) => {
+                for address in &self.breakpoints {
+                    writeln!(out, "{:04}", address)?;
+                }
+            },
+            (Some("watch"), [Some(address)]) => if let Some(range) = span(*address, 1, out)? {
+                self.add_watchpoint(Watchpoint::write(range, WatchAction::Pause), out)?
+            },
+            (Some("watch"), [Some(address), Some(count)]) => if let Some(range) = span(*address, *count, out)? {
+                self.add_watchpoint(Watchpoint::write(range, WatchAction::Pause), out)?
+            },
+            (Some("rwatch"), [Some(address)]) => if let Some(range) = span(*address, 1, out)? {
+                self.add_watchpoint(Watchpoint::read(range, WatchAction::Pause), out)?
+            },
+            (Some("rwatch"), [Some(address), Some(count)]) => if let Some(range) = span(*address, *count, out)? {
+                self.add_watchpoint(Watchpoint::read(range, WatchAction::Pause), out)?
+            },
+            (Some("unwatch"), []) => {
+                self.machine.clear_watchpoints();
+                writeln!(out, "Watchpoints removed.")?;
+            },
+            (Some("smc"), []) => {
+                self.self_modification = !self.self_modification;
+                if self.self_modification {
+                    self.machine.detect_self_modification(Some(WatchAction::Pause));
+                    writeln!(out, "Pausing on writes to executed code.")?;
+                } else {
+                    self.machine.detect_self_modification(None);
+                    writeln!(out, "No longer checking writes to executed code.")?;
+                }
+            },
+            (Some("memory"), [Some(address)]) | (Some("x"), [Some(address)]) => {
+                writeln!(out, "{:04}: {}", address, self.machine.read_memory(*address))?;
+            },
+            (Some("memory"), [Some(address), Some(count)]) | (Some("x"), [Some(address), Some(count)]) => {
+                for address in span(*address, *count, out)?.unwrap_or(0..0) {
+                    writeln!(out, "{:04}: {}", address, self.machine.read_memory(address))?;
+                }
+            },
+            (Some("set"), [Some(address), _]) => match words[2].parse::<W>() {
+                Ok(value) => if let Err(error) = self.machine.write_memory(*address, value) {
+                    writeln!(out, "{}", error)?
+                },
+                Err(_) => writeln!(out, "Invalid value: [{}]", words[2])?
+            },
+            (Some("disassemble"), []) | (Some("d"), []) => self.disassemble(self.machine.address(), 1, out)?,
+            (Some("disassemble"), [Some(address)]) | (Some("d"), [Some(address)]) => self.disassemble(*address, 1, out)?,
+            (Some("disassemble"), [Some(address), Some(count)]) | (Some("d"), [Some(address), Some(count)]) =>
+                self.disassemble(*address, *count, out)?,
+            (Some("input"), _) | (Some("i"), _) if words.len() > 1 => {
+                for word in &words[1..] {
+                    match word.parse::<W>() {
+                        Ok(value) => self.machine.provide_input(value),
+                        Err(_) => writeln!(out, "Invalid value: [{}]", word)?
+                    }
+                }
+            },
+            (Some("output"), []) | (Some("o"), []) => {
+                for value in self.outputs.drain(..) {
+                    writeln!(out, "{}", value)?;
+                }
+            },
+            (Some("info"), []) => self.info(out)?,
+            (Some("help"), _) | (Some("h"), _) => write!(out, "{}", HELP)?,
+            (Some("quit"), _) | (Some("q"), _) => return Ok(false),
+            (Some(_), _) => writeln!(out, "Unknown command: [{}]. Type help for a list of commands.", command.trim())?
+        }
+
+        Ok(true)
+    }
+
+    pub fn run<R: BufRead, Wr: Write>(&mut self, input: R, mut out: Wr) -> io::Result<()> {
+        self.show_current(&mut out)?;
+        write!(out, "(icdb) ")?;
+        out.flush()?;
+
+        for line in input.lines() {
+            if !self.execute(&line?, &mut out)? {
+                return Ok(());
+            }
+
+            write!(out, "(icdb) ")?;
+            out.flush()?;
+        }
+
+        writeln!(out)
+    }
+}
+
+// The `count` addresses from `address`, or nothing if they would run past the last address.
+fn span<Wr: Write>(address: usize, count: usize, out: &mut Wr) -> io::Result<Option<Range<usize>>> {
+    match address.checked_add(count) {
+        Some(end) => Ok(Some(address..end)),
+        None => {
+            writeln!(out, "Address range too large: [{} {}]", address, count)?;
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Debugger;
+    use crate::program::Program;
+
+    fn session(program: &str, commands: &str) -> String {
+        let program: Program<i64> = program.parse().unwrap();
+        let mut debugger = Debugger::new(&program);
+        let mut out: Vec<u8> = vec![];
+
+        for command in commands.lines() {
+            debugger.execute(command, &mut out).unwrap();
+        }
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn step_and_input() {
+        let output = session("3,9,8,9,10,9,4,9,99,-1,8", "step\ninput 8\nstep\nstep 2\noutput\nstep\n");
+
+        assert_eq!(output, "\
+Waiting for input at 0000.
+=> 0002: EQ   [9], [10], [9]
+Output: 1
+=> 0008: HLT
+1
+Program halted.
+");
+    }
+
+    #[test]
+    fn breakpoints() {
+        let output = session("1101,1,2,9,1101,3,4,10,99,0,0", "break 4\nbreakpoints\ncontinue\nmemory 9 2\ncontinue\nmemory 10\n");
+
+        assert_eq!(output, "\
+Breakpoint set at 0004.
+0004
+Breakpoint at 0004.
+=> 0004: ADD  #3, #4, [10]
+0009: 3
+0010: 0
+Program halted.
+0010: 7
+");
+    }
+
+    #[test]
+    fn modify_memory() {
+        let output = session("1101,1,2,5,104,0,99", "set 2 5\ncontinue\ninfo\n");
+
+        assert_eq!(output, "\
+Output: 6
+Program halted.
+address: 7
+relative base: 0
+steps: 3
+status: halted
+input queue: []
+output queue: [6]
+");
+    }
+
+    #[test]
+    fn watchpoints() {
+        let output = session("1101,1,2,9,1101,3,4,10,99,0,0", "watch 10\ncontinue\nrwatch 0\nsmc\ncontinue\n");
+
+        assert_eq!(output, "\
+Watching 0010..0011 for Write.
+Watch: 0004: wrote 7 to [10] (watchpoint 0)
+Paused by watchpoint.
+=> 0008: HLT
+Watching 0000..0001 for Read.
+Pausing on writes to executed code.
+Program halted.
+");
+    }
+
+    #[test]
+    fn disassemble_window() {
+        let output = session("3,9,8,9,10,9,4,9,99,-1,8", "disassemble 0 3\nd 9\n");
+
+        assert_eq!(output, "\
+=> 0000: IN   [9]
+   0002: EQ   [9], [10], [9]
+   0006: OUT  [9]
+   0009: DATA -1
+");
+    }
+
+    #[test]
+    fn errors_and_unknown_commands() {
+        let output = session("42", "jump 3\nstep\n");
+
+        assert_eq!(output, "\
+Unknown command: [jump 3]. Type help for a list of commands.
+Error: Unexpected Operation Code: [42] at address 0
+");
+    }
+
+    #[test]
+    fn oversized_ranges() {
+        let commands = "memory 18446744073709551615 2\nwatch 18446744073709551615\nrwatch 1 18446744073709551615\nd 18446744073709551615 2\n";
+        let output = session("99", commands);
+
+        assert_eq!(output, "\
+Address range too large: [18446744073709551615 2]
+Address range too large: [18446744073709551615 1]
+Address range too large: [1 18446744073709551615]
+   18446744073709551615: DATA 0
+");
+    }
+
+    #[test]
+    fn disassembles_at_the_last_addresses() {
+        let output = session("99", "set 18446744073709551614 1\nd 18446744073709551614 2\n");
+
+        assert_eq!(output, "   18446744073709551614: DATA 1\n   18446744073709551615: DATA 0\n");
+    }
+}
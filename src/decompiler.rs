@@ -0,0 +1,612 @@
+//! Decompiler from Intcode to structured pseudocode, recovering loops and conditionals from jumps.
+use crate::computer::{ComputerError, Machine, OpCode, Operation, ParameterMode, State, Word};
+use crate::control_flow::{successors, Target};
+use crate::disassembler::{decode_line, Line};
+use crate::program::Program;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+
+/// Instructions a guided run may execute before giving up.
+pub const RUN_STEP_LIMIT: u64 = 10_000_000;
+
+const INDENT: &str = "    ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Cell(i128),
+    Relative(i128),
+    Literal(i128)
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Cell(address) => write!(f, "m[{}]", address),
+            Operand::Relative(0) => write!(f, "m[rb]"),
+            Operand::Relative(offset) if *offset < 0 => write!(f, "m[rb{}]", offset),
+            Operand::Relative(offset) => write!(f, "m[rb+{}]", offset),
+            Operand::Literal(value) => write!(f, "{}", value)
+        }
+    }
+}
+
+fn operand<W: Word>(mode: &ParameterMode, value: W) -> Operand {
+    match mode {
+        ParameterMode::Position => Operand::Cell(value.into()),
+        ParameterMode::Immediate => Operand::Literal(value.into()),
+        ParameterMode::Relative => Operand::Relative(value.into())
+    }
+}
+
+// Resolved as `Computer::get_storage_address` does.
+fn destination<W: Word>(mode: &ParameterMode, value: W) -> Operand {
+    match mode {
+        ParameterMode::Relative => Operand::Relative(value.into()),
+        _ => Operand::Cell(value.into())
+    }
+}
+
+fn sum(left: Operand, right: Operand) -> String {
+    match (left, right) {
+        (Operand::Literal(left), Operand::Literal(right)) if left.checked_add(right).is_some() => (left + right).to_string(),
+        (Operand::Literal(0), other) | (other, Operand::Literal(0)) => other.to_string(),
+        (other, Operand::Literal(value)) | (Operand::Literal(value), other) if value < 0 && value != i128::MIN =>
+            format!("{} - {}", other, -value),
+        _ => format!("{} + {}", left, right)
+    }
+}
+
+fn product(left: Operand, right: Operand) -> String {
+    match (left, right) {
+        (Operand::Literal(left), Operand::Literal(right)) if left.checked_mul(right).is_some() => (left * right).to_string(),
+        (Operand::Literal(0), _) | (_, Operand::Literal(0)) => "0".to_owned(),
+        (Operand::Literal(1), other) | (other, Operand::Literal(1)) => other.to_string(),
+        (Operand::Literal(-1), other) | (other, Operand::Literal(-1)) => format!("-{}", other),
+        _ => format!("{} * {}", left, right)
+    }
+}
+
+struct Jump {
+    condition: Operand,
+    if_nonzero: bool,
+    target: Operand,
+    /// Whether the jump is always or never taken, when its condition is a literal.
+    taken: Option<bool>
+}
+
+impl Jump {
+
+    fn decode<W: Word>(line: &Line<W>) -> Option<Jump> {
+        let (operation, operands) = match line {
+            Line::Instruction { operation, operands, .. } => (operation, operands),
+            Line::Data { .. } => return None
+        };
+        let if_nonzero = match operation.opcode {
+            OpCode::JumpIfTrue => true,
+            OpCode::JumpIfFalse => false,
+            _ => return None
+        };
+        let condition = operand(&operation.parameter_one_mode, operands[0]);
+        let taken = match condition {
+            Operand::Literal(value) => Some((value != 0) == if_nonzero),
+            _ => None
+        };
+
+        Some(Jump { condition, if_nonzero, target: operand(&operation.parameter_two_mode, operands[1]), taken })
+    }
+
+    fn immediate_target(&self) -> Option<usize> {
+        match self.target {
+            Operand::Literal(target) if target >= 0 => Some(target as usize),
+            _ => None
+        }
+    }
+
+    // The condition under which execution goes to the target, or falls through when `taken` is false.
+    fn condition(&self, taken: bool) -> String {
+        let operator = if self.if_nonzero == taken { "!=" } else { "==" };
+        format!("{} {} 0", self.condition, operator)
+    }
+}
+
+fn statement<W: Word>(operation: &Operation, operands: &[W]) -> String {
+    let argument = |index: usize| operand(operation.parameter_mode(index), operands[index]);
+    let store = |index: usize| destination(operation.parameter_mode(index), operands[index]);
+
+    match operation.opcode {
+        OpCode::Addition => format!("{} = {}", store(2), sum(argument(0), argument(1))),
+        OpCode::Multiplication => format!("{} = {}", store(2), product(argument(0), argument(1))),
+        OpCode::LessThan => format!("{} = {} < {}", store(2), argument(0), argument(1)),
+        OpCode::Equals => format!("{} = {} == {}", store(2), argument(0), argument(1)),
+        OpCode::Write => format!("{} = input()", store(0)),
+        OpCode::Output => format!("output({})", argument(0)),
+        OpCode::AdjustRelativeBase => format!("rb += {}", argument(0)),
+        OpCode::Halt => "halt()".to_owned(),
+        OpCode::JumpIfTrue | OpCode::JumpIfFalse => unreachable!("Jumps are structured separately."),
+        #[cfg(test)]
+        OpCode::Extension { .. } => unreachable!("Images are decoded without extensions.")
+    }
+}
+
+fn label(address: usize) -> String {
+    format!("label_{:04}", address)
+}
+
+struct Decompilation<W: Word> {
+    image: Vec<W>,
+    /// Reachable lines keyed by address.
+    lines: BTreeMap<usize, Line<W>>,
+    /// Image words replaced by the ones seen executing, keyed by address.
+    rewritten: BTreeMap<usize, W>,
+    /// Rendered lines with their indentation and the address a label for them would name. Empty
+    /// lines only hold a place for a label.
+    output: Vec<(usize, Option<usize>, String)>,
+    gotos: BTreeSet<usize>
+}
+
+impl<W: Word> Decompilation<W> {
+
+    fn new(image: Vec<W>, rewritten: BTreeMap<usize, W>, entries: impl IntoIterator<Item = usize>) -> Self {
+        let mut lines = BTreeMap::new();
+        let mut pending: Vec<usize> = entries.into_iter().filter(|entry| *entry < image.len()).collect();
+
+        while let Some(address) = pending.pop() {
+            if lines.contains_key(&address) {
+                continue;
+            }
+
+            let line = decode_line(|address| image[address], address, image.len());
+            pending.extend(successors(&line, image.len()).into_iter().filter_map(|(_, target)| match target {
+                Target::Block(target) => Some(target),
+                _ => None
+            }));
+            lines.insert(address, line);
+        }
+
+        Decompilation { image, lines, rewritten, output: vec![], gotos: BTreeSet::new() }
+    }
+
+    fn push(&mut self, indent: usize, address: Option<usize>, text: String) {
+        self.output.push((indent, address, text));
+    }
+
+    fn next_line(&self, from: usize, end: usize) -> Option<usize> {
+        if from >= end {
+            return None;
+        }
+
+        self.lines.range(from..end).next().map(|(address, _)| *address)
+    }
+
+    // The last jump in `header..end` that can go back to `header`.
+    fn back_edge(&self, header: usize, end: usize) -> Option<(usize, Jump)> {
+        self.lines.range(header..end).rev()
+            .filter_map(|(address, line)| Jump::decode(line).map(|jump| (*address, jump)))
+            .find(|(_, jump)| jump.immediate_target() == Some(header) && jump.taken != Some(false))
+    }
+
+    fn emit_range(&mut self, start: usize, end: usize, indent: usize) {
+        let mut address = start;
+
+        while let Some(next) = self.next_line(address, end) {
+            address = match self.back_edge(next, end) {
+                Some((jump_address, jump)) => self.emit_loop(next, jump_address, jump, indent),
+                None => self.emit_line(next, end, indent)
+            };
+        }
+
+        // An instruction or loop straddling the end of the range falls through to somewhere past it.
+        if address > end && self.lines.contains_key(&address) {
+            self.gotos.insert(address);
+            self.push(indent, None, format!("goto {};", label(address)));
+        }
+    }
+
+    fn emit_loop(&mut self, header: usize, jump_address: usize, jump: Jump, indent: usize) -> usize {
+        let after = jump_address + 3;
+
+        if jump.taken == Some(true) {
+            // A loop that tests its condition first exits with a forward jump to just past the back edge.
+            let test = Jump::decode(&self.lines[&header]).filter(|test| test.taken.is_none() && test.immediate_target() == Some(after));
+
+            match test {
+                Some(test) if header != jump_address => {
+                    self.push(indent, Some(header), format!("while ({}) {{", test.condition(false)));
+                    self.emit_range(header + 3, jump_address, indent + 1);
+                },
+                _ => {
+                    self.push(indent, Some(header), "loop {".to_owned());
+                    self.emit_range(header, jump_address, indent + 1);
+                }
+            }
+            self.push(indent, None, "}".to_owned());
+        } else {
+            self.push(indent, Some(header), "do {".to_owned());
+            self.emit_range(header, jump_address, indent + 1);
+            self.push(indent, None, format!("}} while ({});", jump.condition(true)));
+        }
+
+        after
+    }
+
+    fn emit_line(&mut self, address: usize, end: usize, indent: usize) -> usize {
+        if let Some(original) = self.rewritten.get(&address) {
+            let text = format!("// {:04}: rewritten at run time, the image holds {}", address, original);
+            self.push(indent, Some(address), text);
+        }
+
+        let (operation, operands, size) = match &self.lines[&address] {
+            Line::Instruction { operation, operands, .. } => (*operation, operands.clone(), operands.len() + 1),
+            Line::Data { value, .. } => {
+                let text = format!("// {:04}: undecodable instruction {}", address, value);
+                self.push(indent, Some(address), text);
+                return address + 1;
+            }
+        };
+        let next = address + size;
+
+        let jump = match Jump::decode(&self.lines[&address]) {
+            Some(jump) => jump,
+            None => {
+                self.push(indent, Some(address), statement(&operation, &operands));
+                return next;
+            }
+        };
+
+        match (jump.taken, jump.immediate_target()) {
+            // Jumps that never go anywhere else leave only a place for labels.
+            (Some(false), _) => {
+                self.push(indent, Some(address), String::new());
+                next
+            },
+            (Some(true), Some(target)) if target == next => {
+                self.push(indent, Some(address), String::new());
+                next
+            },
+            (None, Some(target)) if target >= next && target <= end => self.emit_if(address, next, target, end, &jump, indent),
+            (taken, target) => {
+                let destination = match target {
+                    Some(target) if self.lines.contains_key(&target) => {
+                        self.gotos.insert(target);
+                        label(target)
+                    },
+                    _ => jump.target.to_string()
+                };
+                let text = match taken {
+                    Some(_) => format!("goto {};", destination),
+                    None => format!("if ({}) goto {};", jump.condition(true), destination)
+                };
+
+                self.push(indent, Some(address), text);
+                next
+            }
+        }
+    }
+
+    // A forward jump over `then_start..target`, with an else branch when that range ends by jumping further on.
+    fn emit_if(&mut self, address: usize, then_start: usize, target: usize, end: usize, jump: &Jump, indent: usize) -> usize {
+        let skip = self.lines.range(then_start..target).next_back()
+            .and_then(|(last, line)| Jump::decode(line).map(|jump| (*last, jump)))
+            .filter(|(last, skip)| last + 3 == target && skip.taken == Some(true))
+            .and_then(|(last, skip)| skip.immediate_target().map(|join| (last, join)))
+            .filter(|(_, join)| *join > target && *join <= end);
+
+        self.push(indent, Some(address), format!("if ({}) {{", jump.condition(false)));
+
+        match skip {
+            Some((last, join)) => {
+                self.emit_range(then_start, last, indent + 1);
+                self.push(indent, None, "} else {".to_owned());
+                self.emit_range(target, join, indent + 1);
+                self.push(indent, None, "}".to_owned());
+                join
+            },
+            None => {
+                self.emit_range(then_start, target, indent + 1);
+                self.push(indent, None, "}".to_owned());
+                target
+            }
+        }
+    }
+
+    // Cells read or written by position that aren't part of any decoded instruction.
+    fn data_cells(&self) -> BTreeMap<usize, W> {
+        let code: BTreeSet<usize> = self.lines.values()
+            .flat_map(|line| match line {
+                Line::Instruction { address, operands, .. } => *address..*address + operands.len() + 1,
+                Line::Data { address, .. } => *address..*address + 1
+            })
+            .collect();
+
+        self.lines.values()
+            .filter_map(|line| match line {
+                Line::Instruction { operation, operands, .. } => Some((operation, operands)),
+                Line::Data { .. } => None
+            })
+            .flat_map(|(operation, operands)| operands.iter().enumerate().filter_map(move |(index, value)| {
+                let stored = operation.opcode.storage_parameter() == Some(index);
+                match operation.parameter_mode(index) {
+                    ParameterMode::Position => value.to_address(),
+                    ParameterMode::Immediate if stored => value.to_address(),
+                    _ => None
+                }
+            }))
+            .filter(|address| *address < self.image.len() && !code.contains(address))
+            .map(|address| (address, self.image[address]))
+            .collect()
+    }
+
+    fn render(mut self) -> String {
+        let end = self.image.len();
+        self.emit_range(0, end, 0);
+
+        let mut text = String::new();
+        let cells = self.data_cells();
+        if !cells.is_empty() {
+            text.push_str("// Data cells and their initial values:\n");
+            for (address, value) in cells {
+                text.push_str(&format!("//   m[{}] = {}\n", address, value));
+            }
+            text.push('\n');
+        }
+
+        let mut labelled = BTreeSet::new();
+        for (indent, address, line) in &self.output {
+            if let Some(address) = address {
+                if self.gotos.contains(address) && labelled.insert(*address) {
+                    text.push_str(&format!("{}{}:\n", INDENT.repeat(indent.saturating_sub(1)), label(*address)));
+                }
+            }
+            if !line.is_empty() {
+                text.push_str(&format!("{}{}\n", INDENT.repeat(*indent), line));
+            }
+        }
+
+        for address in self.gotos.difference(&labelled) {
+            text.push_str(&format!("// {} is inside another instruction\n", label(*address)));
+        }
+
+        text
+    }
+}
+
+/// Decompiles everything statically reachable from address 0.
+pub fn decompile<W: Word>(image: &[W]) -> String {
+    Decompilation::new(image.to_vec(), BTreeMap::new(), vec![0]).render()
+}
+
+/// Runs the program on `inputs` until it halts or wants more, then decompiles the words it actually
+/// executed. This follows self-modifying code, which is undecodable in the original image. An
+/// instruction rewritten after it first ran is shown as it was first executed.
+pub fn decompile_run<W: Word>(program: &Program<W>, inputs: &[W]) -> Result<String, ComputerError> {
+    let mut machine = Machine::from_program(program, VecDeque::new(), Vec::new());
+    machine.set_step_limit(Some(RUN_STEP_LIMIT));
+    for input in inputs {
+        machine.provide_input(*input);
+    }
+
+    let mut image = program.image().to_vec();
+    let mut executed: BTreeMap<usize, W> = BTreeMap::new();
+    let mut entries = BTreeSet::new();
+    entries.insert(0);
+
+    while machine.termination().is_none() {
+        let address = machine.address();
+        let operation = machine.get_current_operation()?;
+
+        entries.insert(address);
+        for address in address..address + operation.opcode.instruction_size() {
+            if address < image.len() {
+                executed.entry(address).or_insert_with(|| machine.read_memory(address));
+            }
+        }
+
+        if let State::NeedsInput = machine.step()? {
+            break;
+        }
+    }
+
+    let mut rewritten = BTreeMap::new();
+    for (address, value) in executed {
+        if image[address] != value {
+            rewritten.insert(address, image[address]);
+            image[address] = value;
+        }
+    }
+
+    Ok(Decompilation::new(image, rewritten, entries).render())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompile, decompile_run};
+    use crate::assembler::assemble;
+    use crate::program::Program;
+
+    fn source(source: &str) -> String {
+        decompile(&assemble::<i64>(source).unwrap())
+    }
+
+    #[test]
+    fn straight_line() {
+        assert_eq!(decompile(&[1,9,10,3,2,3,11,0,99,30,40,50]), "\
+// Data cells and their initial values:
+//   m[9] = 30
+//   m[10] = 40
+//   m[11] = 50
+
+m[3] = m[9] + m[10]
+m[0] = m[3] * m[11]
+halt()
+");
+    }
+
+    #[test]
+    fn simplifies_literals() {
+        assert_eq!(decompile(&[1101,2,3,0,1001,0,-5,0,1002,0,1,0,1102,4,5,0,21101,0,7,-2,109,3,204,0,99]), "\
+m[0] = 5
+m[0] = m[0] - 5
+m[0] = m[0]
+m[0] = 20
+m[rb-2] = 7
+rb += 3
+output(m[rb])
+halt()
+");
+    }
+
+    #[test]
+    fn if_else() {
+        let decompiled = source("
+    in   [x]
+    lt   [x], #8, [t]
+    jz   [t], #big
+    out  #1
+    jnz  #1, #done
+big:
+    out  #2
+done:
+    hlt
+x:  data 0
+t:  data 0
+");
+
+        assert_eq!(decompiled, "\
+// Data cells and their initial values:
+//   m[17] = 0
+//   m[18] = 0
+
+m[17] = input()
+m[18] = m[17] < 8
+if (m[18] != 0) {
+    output(1)
+} else {
+    output(2)
+}
+halt()
+");
+    }
+
+    #[test]
+    fn loops() {
+        let decompiled = source("
+    in   [n]
+top:
+    jz   [n], #end
+    out  [n]
+    add  [n], #-1, [n]
+    jz   #0, #top
+end:
+again:
+    in   [n]
+    jnz  [n], #again
+forever:
+    out  #0
+    jnz  #1, #forever
+n:  data 0
+");
+
+        assert_eq!(decompiled, "\
+// Data cells and their initial values:
+//   m[24] = 0
+
+m[24] = input()
+while (m[24] != 0) {
+    output(m[24])
+    m[24] = m[24] - 1
+}
+do {
+    m[24] = input()
+} while (m[24] != 0);
+loop {
+    output(0)
+}
+");
+    }
+
+    #[test]
+    fn gotos() {
+        // Leaves the loop from its middle, then jumps through memory.
+        let decompiled = source("
+top:
+    in   [x]
+    jz   [x], #out
+    jnz  #1, #top
+out:
+    jnz  [x], [x]
+    hlt
+x:  data 0
+");
+
+        assert_eq!(decompiled, "\
+// Data cells and their initial values:
+//   m[12] = 0
+
+loop {
+    m[12] = input()
+    if (m[12] == 0) goto label_0008;
+}
+label_0008:
+if (m[12] != 0) goto m[12];
+halt()
+");
+    }
+
+    #[test]
+    fn straddling_ranges() {
+        // The addition at 3 runs past the end of the if, which jumps to its middle.
+        let program: Program<i64> = "1006,10,5,1101,1,99,0,99,99,99,0".parse().unwrap();
+
+        assert_eq!(decompile(program.image()), "\
+// Data cells and their initial values:
+//   m[10] = 0
+
+if (m[10] != 0) {
+    m[0] = 100
+    goto label_0007;
+}
+halt()
+label_0007:
+halt()
+");
+    }
+
+    #[test]
+    fn self_modifying() {
+        // Adds its input to the opcode at address 6, which only decodes once it has run.
+        let program: Program<i64> = "3,9,1,9,6,6,1100,5,99,0".parse().unwrap();
+
+        assert_eq!(decompile(program.image()), "\
+// Data cells and their initial values:
+//   m[9] = 0
+
+m[9] = input()
+m[6] = m[9] + m[6]
+// 0006: undecodable instruction 1100
+");
+        assert_eq!(decompile_run(&program, &[4]).unwrap(), "\
+// Data cells and their initial values:
+//   m[9] = 0
+
+m[9] = input()
+m[6] = m[9] + m[6]
+// 0006: rewritten at run time, the image holds 1100
+output(5)
+halt()
+");
+    }
+
+    #[test]
+    fn puzzle_inputs() {
+        let two: Program<i64> = include_str!("../inputs/two.txt").trim().parse().unwrap();
+        let five: Program<i64> = include_str!("../inputs/five.txt").trim().parse().unwrap();
+
+        assert!(decompile(two.image()).ends_with("halt()\n"));
+        for input in &[1, 5] {
+            let decompiled = decompile_run(&five, &[*input]).unwrap();
+
+            assert!(decompiled.contains("output("));
+        }
+        assert!(decompile_run(&five, &[5]).unwrap().contains("if ("));
+    }
+}
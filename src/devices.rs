@@ -0,0 +1,284 @@
+//! Peripherals mapped onto address ranges, which a computer reads and writes in place of memory.
+use crate::computer::{ComputerError, Word};
+use crate::rng::Rng;
+use std::convert::TryInto;
+use std::io::Write;
+use std::time::Instant;
+
+/// A peripheral occupying `len` consecutive words, addressed by offset from its first word.
+pub trait Device<W: Word> {
+    fn len(&self) -> usize;
+
+    fn read(&mut self, offset: usize) -> Result<W, ComputerError>;
+
+    /// What a read would return, without its side effects. Used by tracing and inspection.
+    fn peek(&self, offset: usize) -> W;
+
+    fn write(&mut self, offset: usize, value: W) -> Result<(), ComputerError>;
+}
+
+/// The devices attached to a computer, by first address.
+pub struct Bus<W: Word> {
+    devices: Vec<(usize, Box<dyn Device<W> + Send>)>
+}
+
+impl<W: Word> Bus<W> {
+
+    pub fn new() -> Self {
+        Bus { devices: vec![] }
+    }
+
+    /// Panics if the device is empty or overlaps one already attached.
+    pub fn attach(&mut self, start: usize, device: Box<dyn Device<W> + Send>) {
+        let end = start + device.len();
+
+        assert!(start < end, "Devices must occupy at least one word.");
+        for (other, attached) in &self.devices {
+            assert!(end <= *other || other + attached.len() <= start,
+                "Device at {}..{} overlaps the one at {}..{}.", start, end, other, other + attached.len());
+        }
+
+        self.devices.push((start, device));
+    }
+
+    fn find(&self, address: usize) -> Option<usize> {
+        self.devices.iter().position(|(start, device)| (*start..start + device.len()).contains(&address))
+    }
+
+    pub fn read(&mut self, address: usize) -> Option<Result<W, ComputerError>> {
+        let index = self.find(address)?;
+        let (start, device) = &mut self.devices[index];
+        Some(device.read(address - *start))
+    }
+
+    pub fn peek(&self, address: usize) -> Option<W> {
+        let (start, device) = &self.devices[self.find(address)?];
+        Some(device.peek(address - start))
+    }
+
+    pub fn write(&mut self, address: usize, value: W) -> Option<Result<(), ComputerError>> {
+        let index = self.find(address)?;
+        let (start, device) = &mut self.devices[index];
+        Some(device.write(address - *start, value))
+    }
+}
+
+// Characters for pixel values, chosen to suit the day 13 arcade tiles.
+const PALETTE: [char; 5] = [' ', '#', '+', '-', 'o'];
+
+/// A `width` by `height` grid of pixels in row order, followed by a control word.
+/// Writing any value to the control word draws the frame to the writer.
+pub struct Framebuffer<W: Word, Wr: Write> {
+    width: usize,
+    height: usize,
+    pixels: Vec<W>,
+    writer: Wr
+}
+
+impl<W: Word, Wr: Write> Framebuffer<W, Wr> {
+
+    /// Panics if either dimension is zero.
+    pub fn new(width: usize, height: usize, writer: Wr) -> Self {
+        assert!(width > 0 && height > 0, "Framebuffer of {} by {} pixels is empty.", width, height);
+        Framebuffer { width, height, pixels: vec![W::from(0); width * height], writer }
+    }
+
+    /// Pixels outside the palette are drawn as `?`.
+    pub fn render(&self) -> String {
+        let mut frame = String::with_capacity((self.width + 1) * self.height);
+
+        for row in self.pixels.chunks(self.width) {
+            for pixel in row {
+                let index = TryInto::<usize>::try_into(*pixel).ok().filter(|index| *index < PALETTE.len());
+                frame.push(index.map_or('?', |index| PALETTE[index]));
+            }
+            frame.push('\n');
+        }
+
+        frame
+    }
+}
+
+impl<W: Word, Wr: Write> Device<W> for Framebuffer<W, Wr> {
+    fn len(&self) -> usize {
+        self.pixels.len() + 1
+    }
+
+    fn read(&mut self, offset: usize) -> Result<W, ComputerError> {
+        Ok(self.peek(offset))
+    }
+
+    fn peek(&self, offset: usize) -> W {
+        self.pixels.get(offset).copied().unwrap_or_else(|| W::from(0))
+    }
+
+    fn write(&mut self, offset: usize, value: W) -> Result<(), ComputerError> {
+        if offset < self.pixels.len() {
+            self.pixels[offset] = value;
+            return Ok(());
+        }
+
+        let frame = self.render();
+        self.writer.write_all(frame.as_bytes())
+            .and_then(|_| self.writer.flush())
+            .map_err(ComputerError::Output)
+    }
+}
+
+/// A single word counting milliseconds since the timer was created or last written.
+pub struct Timer {
+    start: Instant
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Timer { start: Instant::now() }
+    }
+}
+
+impl<W: Word> Device<W> for Timer {
+    fn len(&self) -> usize {
+        1
+    }
+
+    fn read(&mut self, offset: usize) -> Result<W, ComputerError> {
+        Ok(self.peek(offset))
+    }
+
+    fn peek(&self, _: usize) -> W {
+        W::from(self.start.elapsed().as_millis().min(i32::MAX as u128) as i32)
+    }
+
+    fn write(&mut self, _: usize, _: W) -> Result<(), ComputerError> {
+        self.start = Instant::now();
+        Ok(())
+    }
+}
+
+/// A single word giving a new non-negative random number on every read. Writing reseeds it.
+pub struct Random {
+    rng: Rng
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random { rng: Rng::new(seed) }
+    }
+}
+
+fn draw(rng: &mut Rng) -> i32 {
+    rng.below(i32::MAX as u64 + 1) as i32
+}
+
+impl<W: Word> Device<W> for Random {
+    fn len(&self) -> usize {
+        1
+    }
+
+    fn read(&mut self, _: usize) -> Result<W, ComputerError> {
+        Ok(W::from(draw(&mut self.rng)))
+    }
+
+    fn peek(&self, _: usize) -> W {
+        W::from(draw(&mut self.rng.clone()))
+    }
+
+    fn write(&mut self, _: usize, value: W) -> Result<(), ComputerError> {
+        self.rng = Rng::new(Into::<i128>::into(value) as u64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Device, Framebuffer, Random, Timer};
+    use crate::computer::{Machine, State, Termination};
+    use crate::program::Program;
+    use std::collections::VecDeque;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Screen(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Screen {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buffer)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn machine(program: &str) -> Machine<i64, VecDeque<i64>, Vec<i64>> {
+        let program: Program<i64> = program.parse().unwrap();
+        Machine::from_program(&program, VecDeque::new(), vec![])
+    }
+
+    #[test]
+    fn framebuffer() {
+        let screen = Screen::default();
+        let mut outputs = vec![];
+
+        // Sets three pixels, reads one back, then draws the frame.
+        let program: Program<i64> = "1101,1,0,100,1101,4,0,101,1101,1,0,105,4,101,1101,0,0,106,99".parse().unwrap();
+        let mut machine = Machine::from_program(&program, VecDeque::new(), &mut outputs);
+        machine.attach_device(100, Box::new(Framebuffer::new(3, 2, screen.clone())));
+
+        assert_eq!(machine.compute().unwrap(), Termination::Halted);
+        assert_eq!(machine.read_memory(101), 4);
+        assert!(machine.snapshot().memory.is_empty());
+        assert_eq!(outputs, vec![4]);
+        assert_eq!(std::str::from_utf8(&screen.0.lock().unwrap()).unwrap(), "#o \n  #\n");
+    }
+
+    #[test]
+    fn random() {
+        let mut expected = Random::new(9);
+        let first: i64 = expected.read(0).unwrap();
+        let second: i64 = expected.read(0).unwrap();
+
+        let mut machine = machine("4,50,4,50,99");
+        machine.attach_device(50, Box::new(Random::new(9)));
+
+        assert_eq!(machine.read_memory(50), first);
+        assert_eq!(machine.read_memory(50), first);
+        assert_eq!(machine.run_until_io().unwrap(), State::Output(first));
+        assert_eq!(machine.run_until_io().unwrap(), State::Output(second));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn polling_is_not_a_loop() {
+        // Waits for the timer to tick, which loop detection would otherwise stop.
+        let mut machine = machine("1106,20,0,99");
+        machine.attach_device(20, Box::new(Timer::new()));
+        machine.detect_loops(true);
+
+        assert_eq!(machine.compute().unwrap(), Termination::Halted);
+    }
+
+    #[test]
+    fn writes_reach_devices() {
+        let mut machine = machine("99");
+        machine.attach_device(10, Box::new(Random::new(1)));
+        machine.write_memory(10, 9).unwrap();
+
+        assert_eq!(machine.read_memory(10), Random::new(9).read(0).unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "Device at 12..13 overlaps the one at 10..17.")]
+    fn rejects_overlapping_devices() {
+        let mut machine = machine("99");
+        machine.attach_device(10, Box::new(Framebuffer::new(3, 2, io::sink())));
+        machine.attach_device(12, Box::new(Timer::new()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Framebuffer of 0 by 2 pixels is empty.")]
+    fn rejects_empty_framebuffers() {
+        Framebuffer::<i64, _>::new(0, 2, io::sink());
+    }
+}
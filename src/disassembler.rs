@@ -0,0 +1,156 @@
+//! Disassembler producing an annotated listing of an Intcode image.
+use crate::computer::{OpCode, Operation, ParameterMode, Word};
+use std::fmt;
+
+pub fn mnemonic(opcode: &OpCode) -> &'static str {
+    match opcode {
+        OpCode::Addition => "ADD",
+        OpCode::Multiplication => "MUL",
+        OpCode::Write => "IN",
+        OpCode::Output => "OUT",
+        OpCode::JumpIfTrue => "JNZ",
+        OpCode::JumpIfFalse => "JZ",
+        OpCode::LessThan => "LT",
+        OpCode::Equals => "EQ",
+        OpCode::AdjustRelativeBase => "ARB",
+        OpCode::Halt => "HLT",
+        #[cfg(test)]
+        OpCode::Extension { mnemonic: name, .. } => name
+    }
+}
+
+pub fn format_operand<W: Word>(mode: &ParameterMode, value: W) -> String {
+    match mode {
+        ParameterMode::Position => format!("[{}]", value),
+        ParameterMode::Immediate => format!("#{}", value),
+        ParameterMode::Relative => if value < W::from(0) {
+            format!("[rb{}]", value)
+        } else {
+            format!("[rb+{}]", value)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Line<W: Word> {
+    Instruction {
+        address: usize,
+        operation: Operation,
+        operands: Vec<W>
+    },
+    Data {
+        address: usize,
+        value: W
+    }
+}
+
+impl<W: Word> fmt::Display for Line<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Instruction { address, operation, operands } => {
+                let rendered: Vec<String> = operands.iter().enumerate()
+                    .map(|(index, value)| format_operand(operation.parameter_mode(index), *value))
+                    .collect();
+
+                if rendered.is_empty() {
+                    write!(f, "{:04}: {}", address, mnemonic(&operation.opcode))
+                } else {
+                    write!(f, "{:04}: {:<5}{}", address, mnemonic(&operation.opcode), rendered.join(", "))
+                }
+            },
+            Line::Data { address, value } => write!(f, "{:04}: DATA {}", address, value)
+        }
+    }
+}
+
+impl<W: Word> Line<W> {
+    pub fn size(&self) -> usize {
+        match self {
+            Line::Instruction { operands, .. } => operands.len() + 1,
+            Line::Data { .. } => 1
+        }
+    }
+}
+
+// Anything that fails to decode, or would run past `end` or the last address, is shown as data.
+pub fn decode_line<W: Word, F: Fn(usize) -> W>(read: F, address: usize, end: usize) -> Line<W> {
+    match Operation::decode(read(address)) {
+        Ok(operation) if address.checked_add(operation.parameter_count()).is_some_and(|last| last < end) => {
+            let operands = (1..=operation.parameter_count()).map(|offset| read(address + offset)).collect();
+
+            Line::Instruction { address, operation, operands }
+        },
+        _ => Line::Data { address, value: read(address) }
+    }
+}
+
+// Linear sweep over the whole image.
+pub fn disassemble<W: Word>(image: &[W]) -> Vec<Line<W>> {
+    let mut lines = vec![];
+    let mut address = 0;
+
+    while address < image.len() {
+        let line = decode_line(|address| image[address], address, image.len());
+
+        address += line.size();
+        lines.push(line);
+    }
+
+    lines
+}
+
+pub fn listing<W: Word>(image: &[W]) -> String {
+    disassemble(image).iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, listing, Line};
+
+    #[test]
+    fn modes() {
+        let image = [1002,4,3,4,33,109,-1,204,5,21101,2,3,-4,99];
+
+        assert_eq!(listing(&image), "\
+0000: MUL  [4], #3, [4]
+0004: DATA 33
+0005: ARB  #-1
+0007: OUT  [rb+5]
+0009: ADD  #2, #3, [rb-4]
+0013: HLT
+");
+    }
+
+    #[test]
+    fn data_regions() {
+        let image = [3,9,8,9,10,9,4,9,99,-1,8];
+
+        assert_eq!(listing(&image), "\
+0000: IN   [9]
+0002: EQ   [9], [10], [9]
+0006: OUT  [9]
+0008: HLT
+0009: DATA -1
+0010: DATA 8
+");
+    }
+
+    #[test]
+    fn truncated_instruction() {
+        let lines = disassemble(&[1,0,0]);
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| matches!(line, Line::Data { .. })));
+    }
+
+    #[test]
+    fn jumps() {
+        let image = [1105,1,4,99,6,5,6];
+
+        assert_eq!(listing(&image), "\
+0000: JNZ  #1, #4
+0003: HLT
+0004: JZ   [5], [6]
+");
+    }
+}
@@ -0,0 +1,265 @@
+//! Extra instructions a computer can be taught, alongside the nine built-in opcodes.
+//! Only built for tests, since no subcommand registers extensions yet.
+use crate::computer::{ComputerError, Word};
+
+/// Where execution continues once an extension instruction has run.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Flow {
+    /// The instruction after this one.
+    Next,
+    Jump(usize),
+    Halt
+}
+
+/// The machine state an extension can read and write while its instruction runs.
+pub trait Context<W: Word> {
+    /// Address of the instruction being executed.
+    fn address(&self) -> usize;
+
+    fn relative_base(&self) -> W;
+
+    fn set_relative_base(&mut self, value: W);
+
+    /// Parameter `index` resolved through its mode, as a built-in instruction would read it.
+    fn parameter(&mut self, index: usize) -> Result<W, ComputerError>;
+
+    /// Stores to the address named by parameter `index`.
+    fn store(&mut self, index: usize, value: W) -> Result<(), ComputerError>;
+
+    /// Reads and writes go through any device mapped at `address`.
+    fn read_memory(&mut self, address: usize) -> Result<W, ComputerError>;
+
+    fn write_memory(&mut self, address: usize, value: W) -> Result<(), ComputerError>;
+
+    /// Reported like the output of `OUT`. Several values from one instruction are reported in order.
+    fn output(&mut self, value: W);
+}
+
+/// A custom instruction, registered with `Computer::add_extension`.
+pub trait Extension<W: Word> {
+    /// The two digit opcode, which must not be one of the built-in ones.
+    fn opcode(&self) -> i32;
+
+    fn mnemonic(&self) -> &'static str;
+
+    /// At most three, since an instruction word only has room for three parameter modes.
+    fn parameter_count(&self) -> usize;
+
+    /// Words to skip when the instruction falls through, which may include data after the parameters.
+    fn instruction_size(&self) -> usize {
+        self.parameter_count() + 1
+    }
+
+    /// Index of the parameter naming the address the instruction stores to, if any.
+    fn storage_parameter(&self) -> Option<usize> {
+        None
+    }
+
+    fn execute(&mut self, context: &mut dyn Context<W>) -> Result<Flow, ComputerError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Context, Extension, Flow};
+    use crate::computer::{ComputerError, Machine, State, Termination};
+    use crate::disassembler::mnemonic;
+    use crate::program::Program;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    // DBG a: records its parameter on the host side.
+    struct Debug(Arc<Mutex<Vec<i64>>>);
+
+    impl Extension<i64> for Debug {
+        fn opcode(&self) -> i32 { 20 }
+        fn mnemonic(&self) -> &'static str { "DBG" }
+        fn parameter_count(&self) -> usize { 1 }
+
+        fn execute(&mut self, context: &mut dyn Context<i64>) -> Result<Flow, ComputerError> {
+            let value = context.parameter(0)?;
+            self.0.lock().unwrap().push(value);
+            Ok(Flow::Next)
+        }
+    }
+
+    // SQ a, b: stores a squared in b.
+    struct Square;
+
+    impl Extension<i64> for Square {
+        fn opcode(&self) -> i32 { 21 }
+        fn mnemonic(&self) -> &'static str { "SQ" }
+        fn parameter_count(&self) -> usize { 2 }
+        fn storage_parameter(&self) -> Option<usize> { Some(1) }
+
+        fn execute(&mut self, context: &mut dyn Context<i64>) -> Result<Flow, ComputerError> {
+            let value = context.parameter(0)?;
+            context.store(1, value * value)?;
+            Ok(Flow::Next)
+        }
+    }
+
+    // EMIT n, followed by n inline words: outputs their sum and skips over them.
+    struct Emit;
+
+    impl Extension<i64> for Emit {
+        fn opcode(&self) -> i32 { 22 }
+        fn mnemonic(&self) -> &'static str { "EMIT" }
+        fn parameter_count(&self) -> usize { 1 }
+        fn instruction_size(&self) -> usize { 4 }
+
+        fn execute(&mut self, context: &mut dyn Context<i64>) -> Result<Flow, ComputerError> {
+            let start = context.address() + 2;
+            let count = context.parameter(0)? as usize;
+            let sum = (start..start + count).map(|address| context.read_memory(address)).sum::<Result<i64, _>>()?;
+            context.output(sum);
+            Ok(Flow::Next)
+        }
+    }
+
+    // SPLIT n, followed by n inline words: outputs each of them and skips over them.
+    struct Split;
+
+    impl Extension<i64> for Split {
+        fn opcode(&self) -> i32 { 24 }
+        fn mnemonic(&self) -> &'static str { "SPLIT" }
+        fn parameter_count(&self) -> usize { 1 }
+        fn instruction_size(&self) -> usize { 4 }
+
+        fn execute(&mut self, context: &mut dyn Context<i64>) -> Result<Flow, ComputerError> {
+            let start = context.address() + 2;
+            for address in start..start + context.parameter(0)? as usize {
+                let value = context.read_memory(address)?;
+                context.output(value);
+            }
+            Ok(Flow::Next)
+        }
+    }
+
+    // CALL a: jumps to a, pushing the return address onto a stack at the relative base.
+    struct Call;
+
+    impl Extension<i64> for Call {
+        fn opcode(&self) -> i32 { 23 }
+        fn mnemonic(&self) -> &'static str { "CALL" }
+        fn parameter_count(&self) -> usize { 1 }
+
+        fn execute(&mut self, context: &mut dyn Context<i64>) -> Result<Flow, ComputerError> {
+            let target = context.parameter(0)?;
+            let base = context.relative_base() as usize;
+            context.write_memory(base, (context.address() + 2) as i64)?;
+            context.set_relative_base(context.relative_base() + 1);
+            Ok(if target < 0 { Flow::Halt } else { Flow::Jump(target as usize) })
+        }
+    }
+
+    type Test = Machine<i64, VecDeque<i64>, Vec<i64>>;
+
+    fn run(program: &str, extensions: Vec<Box<dyn Extension<i64> + Send>>) -> Result<(Vec<i64>, Test), ComputerError> {
+        let program: Program<i64> = program.parse().unwrap();
+        let mut machine = Machine::from_program(&program, VecDeque::new(), vec![]);
+        for extension in extensions {
+            machine.add_extension(extension);
+        }
+
+        let mut outputs = vec![];
+        loop {
+            match machine.run_until_io()? {
+                State::Output(value) => outputs.push(value),
+                State::Terminated(_) => return Ok((outputs, machine)),
+                state => panic!("Unexpected state: {:?}", state)
+            }
+        }
+    }
+
+    #[test]
+    fn debug_print() {
+        let seen = Arc::new(Mutex::new(vec![]));
+
+        // DBG #5, DBG [6], HLT, 7
+        let (outputs, _) = run("120,5,20,6,99,0,7", vec![Box::new(Debug(seen.clone()))]).unwrap();
+
+        assert!(outputs.is_empty());
+        assert_eq!(*seen.lock().unwrap(), vec![5, 7]);
+    }
+
+    #[test]
+    fn stores_through_modes() {
+        // ARB #20, SQ #3, [rb+1], SQ [21], [22], OUT [22], HLT
+        let (outputs, machine) = run("109,20,2121,3,1,21,21,22,4,22,99", vec![Box::new(Square)]).unwrap();
+
+        assert_eq!(outputs, vec![81]);
+        assert_eq!(machine.read_memory(21), 9);
+    }
+
+    #[test]
+    fn custom_size_and_output() {
+        // EMIT #2, 30, 12, OUT #1, HLT
+        let (outputs, _) = run("122,2,30,12,104,1,99", vec![Box::new(Emit)]).unwrap();
+
+        assert_eq!(outputs, vec![42, 1]);
+    }
+
+    #[test]
+    fn several_outputs() {
+        // SPLIT #2, 30, 12, HLT
+        let (outputs, machine) = run("124,2,30,12,99", vec![Box::new(Split)]).unwrap();
+
+        assert_eq!(outputs, vec![30, 12]);
+        assert_eq!(machine.termination(), Some(Termination::Halted));
+    }
+
+    #[test]
+    fn jumps_and_halts() {
+        // ARB #20, CALL #7, CALL #-1, HLT, OUT [20], JZ #0, [20]
+        let (outputs, machine) = run("109,20,123,7,123,-1,99,4,20,106,0,20", vec![Box::new(Call)]).unwrap();
+
+        assert_eq!(outputs, vec![4]);
+        assert_eq!(machine.termination(), Some(Termination::Halted));
+        assert_eq!((machine.read_memory(20), machine.read_memory(21)), (4, 6));
+    }
+
+    #[test]
+    fn decodes_with_extensions() {
+        let program: Program<i64> = "21,6,5,99,0,0,4".parse().unwrap();
+        let mut machine = Machine::from_program(&program, VecDeque::new(), vec![]);
+
+        assert!(matches!(machine.get_current_operation(), Err(ComputerError::UnknownOpcode { address: 0, opcode: 21 })));
+
+        machine.add_extension(Box::new(Square));
+        let operation = machine.get_current_operation().unwrap();
+
+        assert_eq!(mnemonic(&operation.opcode), "SQ");
+        assert_eq!(operation.parameter_count(), 2);
+        assert_eq!(machine.compute().unwrap(), Termination::Halted);
+        assert_eq!(machine.read_memory(5), 16);
+    }
+
+    #[test]
+    fn rewritten_extension_instruction() {
+        let seen = Arc::new(Mutex::new(vec![]));
+
+        // DBG #5, JNZ [17], #16, ADD #104, #0, [0], ADD #1, #0, [17], JNZ #1, #0, HLT, 0
+        // The second pass runs the rewritten word at address 0 as OUT #5.
+        let program = "120,5,1005,17,16,1101,104,0,0,1101,1,0,17,1105,1,0,99,0";
+        let (outputs, _) = run(program, vec![Box::new(Debug(seen.clone()))]).unwrap();
+
+        assert_eq!(outputs, vec![5]);
+        assert_eq!(*seen.lock().unwrap(), vec![5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Opcode 7 is built in.")]
+    fn rejects_built_in_opcodes() {
+        struct Shadow;
+
+        impl Extension<i64> for Shadow {
+            fn opcode(&self) -> i32 { 7 }
+            fn mnemonic(&self) -> &'static str { "LT" }
+            fn parameter_count(&self) -> usize { 0 }
+            fn execute(&mut self, _: &mut dyn Context<i64>) -> Result<Flow, ComputerError> { Ok(Flow::Next) }
+        }
+
+        let program: Program<i64> = "99".parse().unwrap();
+        Machine::from_program(&program, VecDeque::new(), vec![]).add_extension(Box::new(Shadow));
+    }
+}
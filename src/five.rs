@@ -1,10 +1,11 @@
 //! Day 5
 use crate::computer;
+use crate::streams::{TextInput, TextOutput};
 use std::io::{self};
 
-pub fn solve_part_one(input: &mut [i32]) {
+pub fn solve_part_one<W: computer::Word>(input: &mut [W]) -> Result<computer::Termination, computer::ComputerError> {
     let stdin = io::stdin();
-    let mut computer = computer::Computer::new(input, stdin.lock(), io::stdout());
+    let mut computer = computer::Computer::new(input, TextInput::new(stdin.lock()), TextOutput::new(io::stdout()));
 
-    computer.compute();
+    computer.compute()
 }
@@ -0,0 +1,468 @@
+//! Differential fuzzing of `Computer` against a deliberately simple reference interpreter.
+use crate::computer::{ComputerError, Machine, Termination};
+use crate::program::Program;
+use crate::rng::Rng;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+/// Instructions either interpreter may run before a program is taken to be looping.
+pub const STEP_LIMIT: u64 = 1_000;
+
+/// A program and the input it is given, written as `program / input`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub program: Vec<i64>,
+    pub input: Vec<i64>
+}
+
+fn join(values: &[i64]) -> String {
+    values.iter().map(i64::to_string).collect::<Vec<String>>().join(",")
+}
+
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} / {}", join(&self.program), join(&self.input))
+    }
+}
+
+impl FromStr for Case {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let words = |text: &str| -> Result<Vec<i64>, String> {
+            text.split(',')
+                .map(str::trim)
+                .filter(|word| !word.is_empty())
+                .map(|word| word.parse().map_err(|_| format!("Invalid word: [{}]", word)))
+                .collect()
+        };
+
+        match line.split('/').collect::<Vec<&str>>().as_slice() {
+            [program, input] => Ok(Case { program: words(program)?, input: words(input)? }),
+            _ => Err(format!("Expected `program / input`: [{}]", line))
+        }
+    }
+}
+
+/// Every case in a regression file, skipping blank lines and `#` comments.
+pub fn parse_cases(text: &str) -> Result<Vec<Case>, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::parse)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ending {
+    Halted,
+    RanOffEnd,
+    InputEof,
+    StepLimit,
+    /// An unknown opcode or parameter mode.
+    BadInstruction { address: usize },
+    OutOfBounds { address: i128, instruction: usize },
+    Overflow { instruction: usize }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub ending: Ending,
+    pub outputs: Vec<i64>,
+    /// Every non-zero word in memory, in address order.
+    pub memory: Vec<(usize, i64)>
+}
+
+// Opcode and parameter modes, or nothing for a word that isn't an instruction.
+fn decode(word: i64) -> Option<(i64, [i64; 3])> {
+    if !(0..30_000).contains(&word) {
+        return None;
+    }
+
+    let modes = [word / 100 % 10, word / 1000 % 10, word / 10000];
+    match (word % 100, modes.iter().all(|mode| *mode <= 2)) {
+        (1..=9, true) | (99, true) => Some((word % 100, modes)),
+        _ => None
+    }
+}
+
+fn size(opcode: i64) -> usize {
+    match opcode {
+        1 | 2 | 7 | 8 => 4,
+        5 | 6 => 3,
+        3 | 4 | 9 => 2,
+        _ => 1
+    }
+}
+
+struct Reference {
+    memory: BTreeMap<usize, i64>,
+    input: VecDeque<i64>,
+    outputs: Vec<i64>,
+    address: usize,
+    relative_base: i64,
+    modes: [i64; 3]
+}
+
+impl Reference {
+
+    fn read(&self, address: usize) -> i64 {
+        self.memory.get(&address).copied().unwrap_or(0)
+    }
+
+    fn to_address(&self, value: i64) -> Result<usize, Ending> {
+        if value < 0 {
+            return Err(Ending::OutOfBounds { address: value.into(), instruction: self.address });
+        }
+
+        Ok(value as usize)
+    }
+
+    // The address a parameter names, treating immediate mode like position mode as stores do.
+    fn target(&self, index: usize) -> Result<usize, Ending> {
+        let word = self.read(self.address + index + 1);
+
+        match self.modes[index] {
+            2 => self.to_address(self.relative_base.checked_add(word).ok_or(Ending::Overflow { instruction: self.address })?),
+            _ => self.to_address(word)
+        }
+    }
+
+    fn parameter(&self, index: usize) -> Result<i64, Ending> {
+        match self.modes[index] {
+            1 => Ok(self.read(self.address + index + 1)),
+            _ => Ok(self.read(self.target(index)?))
+        }
+    }
+
+    // Runs one instruction and returns where it jumped to, or nothing if it falls through.
+    fn execute(&mut self, opcode: i64) -> Result<Option<usize>, Ending> {
+        match opcode {
+            1 | 2 | 7 | 8 => {
+                let (left, right) = (self.parameter(0)?, self.parameter(1)?);
+                let destination = self.target(2)?;
+                let value = match opcode {
+                    1 => left.checked_add(right).ok_or(Ending::Overflow { instruction: self.address })?,
+                    2 => left.checked_mul(right).ok_or(Ending::Overflow { instruction: self.address })?,
+                    7 => (left < right) as i64,
+                    _ => (left == right) as i64
+                };
+                self.memory.insert(destination, value);
+            },
+            3 => {
+                let destination = self.target(0)?;
+                let value = self.input.pop_front().ok_or(Ending::InputEof)?;
+                self.memory.insert(destination, value);
+            },
+            4 => {
+                let value = self.parameter(0)?;
+                self.outputs.push(value);
+            },
+            5 | 6 => {
+                let (condition, destination) = (self.parameter(0)?, self.parameter(1)?);
+                if (condition != 0) == (opcode == 5) {
+                    return self.to_address(destination).map(Some);
+                }
+            },
+            9 => self.relative_base = self.relative_base.checked_add(self.parameter(0)?).ok_or(Ending::Overflow { instruction: self.address })?,
+            _ => return Err(Ending::Halted)
+        }
+
+        Ok(None)
+    }
+}
+
+/// Runs a case one instruction at a time, with memory as a map from address to word.
+pub fn reference(case: &Case) -> Run {
+    let mut machine = Reference {
+        memory: case.program.iter().copied().enumerate().collect(),
+        input: case.input.iter().copied().collect(),
+        outputs: vec![],
+        address: 0,
+        relative_base: 0,
+        modes: [0; 3]
+    };
+    let mut steps = 0;
+
+    let ending = loop {
+        let (opcode, modes) = match decode(machine.read(machine.address)) {
+            Some(decoded) => decoded,
+            None => break Ending::BadInstruction { address: machine.address }
+        };
+        if opcode == 3 && machine.input.is_empty() {
+            break Ending::InputEof;
+        }
+        if steps >= STEP_LIMIT {
+            break Ending::StepLimit;
+        }
+
+        machine.modes = modes;
+        steps += 1;
+        // Falling through past the program ends the run, as does reaching a word never written.
+        let next = machine.address + size(opcode);
+        match machine.execute(opcode) {
+            Ok(None) if machine.address < case.program.len() && next >= case.program.len() => break Ending::RanOffEnd,
+            Ok(target) => machine.address = target.unwrap_or(next),
+            Err(ending) => break ending
+        }
+        if !machine.memory.contains_key(&machine.address) {
+            break Ending::RanOffEnd;
+        }
+    };
+
+    Run {
+        ending,
+        outputs: machine.outputs,
+        memory: machine.memory.into_iter().filter(|(_, value)| *value != 0).collect()
+    }
+}
+
+/// Runs a case through `Computer`, with or without its decoded instruction cache.
+pub fn interpret(case: &Case, cached: bool) -> Run {
+    let program = Program::new(case.program.clone());
+    let mut outputs = vec![];
+    let mut machine = Machine::from_program(&program, case.input.iter().copied().collect::<VecDeque<i64>>(), &mut outputs);
+    machine.set_decode_cache(cached);
+    machine.set_step_limit(Some(STEP_LIMIT));
+
+    let ending = match machine.compute() {
+        Ok(Termination::Halted) => Ending::Halted,
+        Ok(Termination::RanOffEnd) => Ending::RanOffEnd,
+        Err(ComputerError::InputEof) => Ending::InputEof,
+        Err(ComputerError::StepLimit { .. }) => Ending::StepLimit,
+        Err(ComputerError::UnknownOpcode { address, .. }) | Err(ComputerError::InvalidMode { address, .. }) =>
+            Ending::BadInstruction { address },
+        Err(ComputerError::OutOfBounds { address, instruction }) => Ending::OutOfBounds { address, instruction },
+        Err(ComputerError::Overflow { instruction }) => Ending::Overflow { instruction },
+        Err(error) => panic!("Unexpected error from a fuzzed program: {}", error)
+    };
+    let snapshot = machine.snapshot();
+    drop(machine);
+
+    let memory = snapshot.image.iter().copied().enumerate()
+        .chain(snapshot.memory)
+        .filter(|(_, value)| *value != 0)
+        .collect();
+
+    Run { ending, outputs, memory }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub case: Case,
+    pub cached: bool,
+    pub expected: Run,
+    pub actual: Run
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} ({} decode cache)", self.case, if self.cached { "with" } else { "without" })?;
+        writeln!(f, "  reference: {:?}, outputs {:?}", self.expected.ending, self.expected.outputs)?;
+        writeln!(f, "  computer:  {:?}, outputs {:?}", self.actual.ending, self.actual.outputs)?;
+        if self.expected.memory != self.actual.memory {
+            writeln!(f, "  memory differs")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares `Computer` with the reference, with and without the decoded instruction cache.
+pub fn check(case: &Case) -> Option<Divergence> {
+    let expected = reference(case);
+
+    [true, false].iter().find_map(|cached| {
+        let actual = interpret(case, *cached);
+        if actual == expected {
+            return None;
+        }
+
+        Some(Divergence { case: case.clone(), cached: *cached, expected: expected.clone(), actual })
+    })
+}
+
+// A word for parameter `index` of an instruction with this opcode, biased towards meaningful values.
+fn operand(rng: &mut Rng, opcode: i64, index: usize, mode: i64, starts: &[usize], code_len: usize, len: usize) -> i64 {
+    let data = |rng: &mut Rng| rng.range(code_len as i64, len as i64 - 1);
+    let stores = index == 2 || opcode == 3;
+
+    match mode {
+        1 if (opcode == 5 || opcode == 6) && index == 1 && rng.chance(80) =>
+            starts[rng.below(starts.len() as u64) as usize] as i64,
+        1 if opcode == 9 => rng.range(-5, 5),
+        1 => rng.range(-20, 20),
+        2 => rng.range(-4, len as i64 + 4),
+        _ if stores && rng.chance(15) => rng.range(0, code_len as i64 - 1),
+        _ if rng.chance(5) => rng.range(-3, len as i64 + 8),
+        _ => data(rng)
+    }
+}
+
+/// A random program of mostly valid instructions over a small data area, with the occasional
+/// corrupted word, store into its own code or jump outside it.
+pub fn generate(rng: &mut Rng) -> Case {
+    const OPCODES: [i64; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+
+    let count = 1 + rng.below(16) as usize;
+    let mut opcodes: Vec<i64> = (0..count).map(|_| OPCODES[rng.below(OPCODES.len() as u64) as usize]).collect();
+    if rng.chance(80) {
+        opcodes.push(99);
+    }
+
+    let mut starts = vec![];
+    let mut code_len = 0;
+    for opcode in &opcodes {
+        starts.push(code_len);
+        code_len += size(*opcode);
+    }
+    let len = code_len + 8;
+
+    let mut program = vec![];
+    for opcode in &opcodes {
+        let parameters = size(*opcode) - 1;
+        let modes: Vec<i64> = (0..parameters).map(|index| {
+            let stores = index == 2 || *opcode == 3;
+            match rng.below(100) {
+                roll if stores => if roll < 70 { 0 } else if roll < 95 { 2 } else { 1 },
+                roll if roll < 50 => 0,
+                roll if roll < 80 => 1,
+                _ => 2
+            }
+        }).collect();
+
+        let word = opcode + modes.iter().enumerate().map(|(index, mode)| mode * 10_i64.pow(index as u32 + 2)).sum::<i64>();
+        program.push(if rng.chance(3) { rng.range(-5, 40_000) } else { word });
+
+        for (index, mode) in modes.iter().enumerate() {
+            program.push(operand(rng, *opcode, index, *mode, &starts, code_len, len));
+        }
+    }
+    program.extend((code_len..len).map(|_| rng.range(-10, 10)));
+
+    let input = (0..rng.below(4)).map(|_| rng.range(-10, 10)).collect();
+
+    Case { program, input }
+}
+
+/// Shrinks a case while `fails` holds, by dropping input, cutting runs of words and simplifying
+/// what remains.
+pub fn minimize<F: Fn(&Case) -> bool>(case: &Case, fails: F) -> Case {
+    let mut best = case.clone();
+
+    loop {
+        let mut candidates = vec![];
+
+        for index in 0..best.input.len() {
+            let mut candidate = best.clone();
+            candidate.input.remove(index);
+            candidates.push(candidate);
+        }
+
+        let mut chunk = best.program.len() / 2;
+        while chunk > 0 {
+            for start in (0..best.program.len()).step_by(chunk) {
+                let mut candidate = best.clone();
+                candidate.program.drain(start..(start + chunk).min(best.program.len()));
+                if !candidate.program.is_empty() {
+                    candidates.push(candidate);
+                }
+            }
+            chunk /= 2;
+        }
+
+        for (index, word) in best.program.iter().enumerate() {
+            for simpler in &[0, word / 2] {
+                if simpler != word {
+                    let mut candidate = best.clone();
+                    candidate.program[index] = *simpler;
+                    candidates.push(candidate);
+                }
+            }
+        }
+
+        match candidates.into_iter().find(|candidate| fails(candidate)) {
+            Some(smaller) => best = smaller,
+            None => return best
+        }
+    }
+}
+
+/// Checks `iterations` generated cases, returning each divergence with its case minimized.
+pub fn fuzz(seed: u64, iterations: usize) -> Vec<Divergence> {
+    let mut rng = Rng::new(seed);
+
+    (0..iterations)
+        .map(|_| generate(&mut rng))
+        .filter_map(|case| check(&case))
+        .map(|divergence| {
+            let minimized = minimize(&divergence.case, |case| check(case).is_some());
+            check(&minimized).expect("Minimized cases still diverge.")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, fuzz, generate, minimize, parse_cases, reference, Case, Ending};
+    use crate::rng::Rng;
+
+    fn case(program: &[i64], input: &[i64]) -> Case {
+        Case { program: program.to_vec(), input: input.to_vec() }
+    }
+
+    #[test]
+    fn generation_is_reproducible() {
+        assert_eq!(generate(&mut Rng::new(3)), generate(&mut Rng::new(3)));
+    }
+
+    #[test]
+    fn reference_interpreter() {
+        let equals_eight = [3,9,8,9,10,9,4,9,99,-1,8];
+        assert_eq!(reference(&case(&equals_eight, &[8])).outputs, vec![1]);
+        assert_eq!(reference(&case(&equals_eight, &[7])).outputs, vec![0]);
+        assert_eq!(reference(&case(&equals_eight, &[])).ending, Ending::InputEof);
+
+        let quine = [109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+        assert_eq!(reference(&case(&quine, &[])).outputs, quine.to_vec());
+
+        assert_eq!(reference(&case(&[1,0,0,0], &[])).ending, Ending::RanOffEnd);
+        assert_eq!(reference(&case(&[1105,1,0], &[])).ending, Ending::StepLimit);
+        assert_eq!(reference(&case(&[4,-1], &[])).ending, Ending::OutOfBounds { address: -1, instruction: 0 });
+        assert_eq!(reference(&case(&[1,0,0,0,30099], &[])).ending, Ending::BadInstruction { address: 4 });
+        assert_eq!(reference(&case(&[1002,4,-1,0,0], &[])).memory, vec![(1, 4), (2, -1)]);
+        assert_eq!(reference(&case(&[109,1,1101,i64::MAX,1,0,99], &[])).ending, Ending::Overflow { instruction: 2 });
+    }
+
+    #[test]
+    fn parses_cases() {
+        let cases = parse_cases("# A comment\n\n1,2,3 / 4\n99 / \n").unwrap();
+
+        assert_eq!(cases, vec![case(&[1, 2, 3], &[4]), case(&[99], &[])]);
+        assert_eq!(cases[0].to_string(), "1,2,3 / 4");
+        assert!(parse_cases("1,2,3").is_err());
+    }
+
+    #[test]
+    fn minimizes() {
+        let original = case(&[1, 5, 4, 42, 99, 7], &[3, 2]);
+        let minimized = minimize(&original, |case| case.program.contains(&42));
+
+        assert_eq!(minimized, case(&[42], &[]));
+    }
+
+    #[test]
+    fn no_divergence() {
+        for seed in 1..=4 {
+            let divergences = fuzz(seed, 500);
+            assert!(divergences.is_empty(), "{}", divergences[0]);
+        }
+    }
+
+    #[test]
+    fn regressions() {
+        for case in parse_cases(include_str!("../inputs/fuzz_regressions.txt")).unwrap() {
+            assert_eq!(check(&case), None, "{}", case);
+        }
+    }
+}
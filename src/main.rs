@@ -1,18 +1,382 @@
+mod amplifier;
+mod assembler;
+mod benchmark;
 mod computer;
+mod control_flow;
+mod debugger;
+mod decompiler;
+mod devices;
+mod disassembler;
+#[cfg(test)]
+mod extension;
+mod memory;
+mod network;
+mod profile;
+mod program;
+mod rng;
+#[cfg(test)]
+mod sample_native;
+mod transpiler;
+mod two_native;
 mod one;
 mod two;
+mod watch;
+#[allow(clippy::iter_kv_map, clippy::legacy_numeric_constants, clippy::needless_borrow)]
 mod three;
+#[allow(clippy::match_like_matches_macro, clippy::zero_prefixed_literal)]
 mod four;
+mod fuzz;
 mod five;
+#[allow(clippy::collapsible_match, clippy::iter_kv_map, clippy::legacy_numeric_constants, clippy::single_match)]
 mod six;
+mod snapshot;
+mod streams;
+mod trace;
+use std::env;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::prelude::*;
 use std::convert::TryFrom;
 
 fn main() {
-    solve_six_part_one();
-    solve_six_part_two();
+    let args: Vec<String> = env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        Some("amplify") => amplify(&args[2..]),
+        Some("ascii") => ascii(&args[2..]),
+        Some("assemble") => assemble(&args[2..]),
+        Some("benchmark") => benchmark(&args[2..]),
+        Some("cfg") => control_flow_graph(&args[2..]),
+        Some("debug") => debug(&args[2..]),
+        Some("decompile") => decompile(&args[2..]),
+        Some("disassemble") => disassemble(&args[2..]),
+        Some("fuzz") => fuzz(&args[2..]),
+        Some("network") => network(&args[2..]),
+        Some("profile") => profile(&args[2..]),
+        Some("run") => run(&args[2..]),
+        Some("screen") => screen(&args[2..]),
+        Some("trace") => trace(&args[2..]),
+        Some("transpile") => transpile(&args[2..]),
+        _ => {
+            solve_six_part_one();
+            solve_six_part_two();
+        }
+    }
+}
+
+fn amplify(args: &[String]) {
+    let path = args.first().expect("Usage: amplify <program> [--feedback]");
+    let program = load_intcode_program::<i64>(path);
+    let (topology, phases) = match args.get(1).map(String::as_str) {
+        Some("--feedback") => (amplifier::Topology::Feedback, [5, 6, 7, 8, 9]),
+        _ => (amplifier::Topology::Chain, [0, 1, 2, 3, 4])
+    };
+
+    match amplifier::max_signal(&program, &phases, topology) {
+        Ok((phases, signal)) => println!("Max thruster signal: {} from phases {:?}", signal, phases),
+        Err(error) => println!("{}: {}", path, error)
+    }
+}
+
+// Plays a program that talks in text, typing lines at the terminal as its input.
+fn ascii(args: &[String]) {
+    let path = args.first().expect("Usage: ascii <program>");
+    let program = load_intcode_program::<i64>(path);
+    let stdin = std::io::stdin();
+    let mut machine = computer::Machine::from_program(
+        &program,
+        streams::AsciiInput::new(stdin.lock()),
+        streams::AsciiOutput::new(std::io::stdout())
+    );
+
+    if let Err(error) = machine.compute() {
+        println!("{}: {}", path, error);
+    }
+}
+
+fn assemble(args: &[String]) {
+    let path = args.first().expect("Usage: assemble <source>");
+    let mut source = String::new();
+    File::open(path).unwrap().read_to_string(&mut source).expect("Unable to read assembly source.");
+
+    match assembler::assemble::<i64>(&source) {
+        Ok(image) => {
+            let words: Vec<String> = image.iter().map(i64::to_string).collect();
+            println!("{}", words.join(","));
+        },
+        Err(error) => println!("{}: {}", path, error)
+    }
+}
+
+// Times the built in samples, or a program given with its inputs.
+fn benchmark(args: &[String]) {
+    let timings = match args {
+        [] => benchmark::samples().iter()
+            .map(|sample| benchmark::measure(sample.name, &sample.program, &sample.input))
+            .collect(),
+        [path, inputs @ ..] => {
+            let inputs: Vec<i64> = inputs.iter().map(|input| input.parse().expect("Inputs must be numbers.")).collect();
+            benchmark::measure(path, &load_intcode_program(path), &inputs).map(|timing| vec![timing])
+        }
+    };
+
+    match timings {
+        Ok(timings) => benchmark::report(&timings, std::io::stdout()).expect("Unable to write report."),
+        Err(error) => println!("Benchmark failed: {}", error)
+    }
+}
+
+fn control_flow_graph(args: &[String]) {
+    let path = args.first().expect("Usage: cfg <program>");
+    let program = load_intcode_program::<i64>(path);
+
+    print!("{}", control_flow::ControlFlowGraph::build(program.image()).to_dot());
+}
+
+fn debug(args: &[String]) {
+    let path = args.first().expect("Usage: debug <program>");
+    let program = load_intcode_program::<i64>(path);
+    let stdin = std::io::stdin();
+
+    debugger::Debugger::new(&program).run(stdin.lock(), std::io::stdout()).expect("Debugger I/O failed.");
+}
+
+fn decompile(args: &[String]) {
+    let path = args.first().expect("Usage: decompile <program> [--run input...]");
+    let program = load_intcode_program::<i64>(path);
+
+    match args.get(1).map(String::as_str) {
+        Some("--run") => {
+            let inputs: Vec<i64> = args[2..].iter().map(|input| input.parse().expect("Inputs must be numbers.")).collect();
+
+            match decompiler::decompile_run(&program, &inputs) {
+                Ok(decompiled) => print!("{}", decompiled),
+                Err(error) => println!("{}: {}", path, error)
+            }
+        },
+        _ => print!("{}", decompiler::decompile(program.image()))
+    }
+}
+
+fn disassemble(args: &[String]) {
+    let path = args.first().expect("Usage: disassemble <program>");
+    let program = load_intcode_program::<i64>(path);
+
+    print!("{}", disassembler::listing(program.image()));
+}
+
+// Replays the regression file, then appends minimized divergences from new cases to it.
+fn fuzz(args: &[String]) {
+    let usage = "Usage: fuzz [iterations] [seed]";
+    let iterations = args.first().map_or(10_000, |iterations| iterations.parse().expect(usage));
+    let seed = args.get(1).map_or(1, |seed| seed.parse().expect(usage));
+
+    let regressions = std::fs::read_to_string("inputs/fuzz_regressions.txt").expect("Unable to read the regression file.");
+    for case in fuzz::parse_cases(&regressions).expect("Invalid regression file.") {
+        if let Some(divergence) = fuzz::check(&case) {
+            print!("Regression: {}", divergence);
+        }
+    }
+
+    let divergences = fuzz::fuzz(seed, iterations);
+    for divergence in &divergences {
+        print!("{}", divergence);
+    }
+
+    if !divergences.is_empty() {
+        let mut regressions = std::fs::OpenOptions::new()
+            .append(true)
+            .open("inputs/fuzz_regressions.txt")
+            .expect("Unable to open the regression file.");
+
+        for divergence in &divergences {
+            writeln!(regressions, "# Seed {}, reference ended {:?}", seed, divergence.expected.ending).unwrap();
+            writeln!(regressions, "{}", divergence.case).unwrap();
+        }
+    }
+
+    println!("{} cases from seed {}, {} divergences.", iterations, seed, divergences.len());
+}
+
+fn network(args: &[String]) {
+    let path = args.first().expect("Usage: network <program> [size]");
+    let size = args.get(1).map_or(50, |size| size.parse().expect("Network size must be a number."));
+    let program = load_intcode_program::<i64>(path);
+
+    match network::Network::new(&program, size).run() {
+        Ok(report) => {
+            println!("First packet to the NAT: x={} y={}", report.first_packet.x, report.first_packet.y);
+            println!("First repeated wake-up value: {}", report.repeated_wake);
+        },
+        Err(error) => println!("{}: {}", path, error)
+    }
+}
+
+// The report goes to stderr so it doesn't mix with the program's output.
+fn profile(args: &[String]) {
+    let usage = "Usage: profile <program> [--csv <file>]";
+    let path = args.first().expect(usage);
+    let program = load_intcode_program::<i64>(path);
+    let mut machine = computer::Machine::from_program(
+        &program,
+        streams::TextInput::new(std::io::stdin().lock()),
+        streams::TextOutput::new(std::io::stdout())
+    );
+
+    machine.enable_profiling();
+    if let Err(error) = machine.compute() {
+        eprintln!("{}: {}", path, error);
+    }
+
+    let profile = machine.take_profile().expect("Profiling was enabled.");
+    match args.get(1..) {
+        Some([flag, csv]) if flag == "--csv" => {
+            let file = std::io::BufWriter::new(File::create(csv).expect("Unable to create profile file."));
+            profile.write_csv(file).expect("Unable to write profile.");
+        },
+        Some([]) => profile.report(std::io::stderr(), 20).expect("Unable to write profile."),
+        _ => panic!("{}", usage)
+    }
+}
+
+// When input runs out with --save given, the machine is written to a snapshot which --resume continues.
+fn run(args: &[String]) {
+    let usage = "Usage: run <program> [--save <snapshot>] [--detect-loops] | run --resume <snapshot> [--save <snapshot>] [--detect-loops]";
+    let mut program = None;
+    let mut resume = None;
+    let mut save = None;
+    let mut detect_loops = false;
+    let mut words = args.iter();
+
+    while let Some(word) = words.next() {
+        match word.as_str() {
+            "--resume" => resume = Some(words.next().expect(usage)),
+            "--save" => save = Some(words.next().expect(usage)),
+            "--detect-loops" => detect_loops = true,
+            path if program.is_none() => program = Some(path),
+            _ => panic!("{}", usage)
+        }
+    }
+
+    let source = streams::TextInput::new(std::io::stdin().lock());
+    let sink = streams::TextOutput::new(std::io::stdout());
+    let mut machine = match (program, resume) {
+        (None, Some(path)) => {
+            let file = BufReader::new(File::open(path).expect("Unable to open snapshot."));
+            match snapshot::Snapshot::load(file) {
+                Ok(snapshot) => computer::Machine::from_snapshot(&snapshot, source, sink),
+                Err(error) => return println!("{}: {}", path, error)
+            }
+        },
+        (Some(path), None) => computer::Machine::from_program(&load_intcode_program::<i64>(path), source, sink),
+        _ => panic!("{}", usage)
+    };
+    machine.detect_loops(detect_loops);
+
+    match (machine.compute(), save) {
+        (Ok(_), _) => (),
+        (Err(computer::ComputerError::InputEof), Some(path)) => {
+            let file = File::create(path).expect("Unable to create snapshot.");
+            machine.snapshot().save(std::io::BufWriter::new(file)).expect("Unable to write snapshot.");
+            println!("Input ended, machine saved to {}", path);
+        },
+        (Err(error), _) => println!("{}", error)
+    }
+}
+
+// Runs a program with a framebuffer mapped into memory, drawing a frame whenever it writes the control word.
+fn screen(args: &[String]) {
+    let usage = "Usage: screen <program> <address> <width> <height> [--timer <address>] [--random <address> <seed>]";
+    let (path, address, width, height) = match args {
+        [path, address, width, height, ..] =>
+            (path, address.parse().expect(usage), width.parse().expect(usage), height.parse().expect(usage)),
+        _ => panic!("{}", usage)
+    };
+    let program = load_intcode_program::<i64>(path);
+    let mut machine = computer::Machine::from_program(
+        &program,
+        streams::TextInput::new(std::io::stdin().lock()),
+        streams::TextOutput::new(std::io::stdout())
+    );
+    let mut flags = args[4..].iter();
+
+    machine.attach_device(address, Box::new(devices::Framebuffer::new(width, height, std::io::stdout())));
+    while let Some(flag) = flags.next() {
+        let address = flags.next().expect(usage).parse().expect(usage);
+        match flag.as_str() {
+            "--timer" => machine.attach_device(address, Box::new(devices::Timer::new())),
+            "--random" => {
+                let seed = flags.next().expect(usage).parse().expect(usage);
+                machine.attach_device(address, Box::new(devices::Random::new(seed)));
+            },
+            _ => panic!("{}", usage)
+        }
+    }
+
+    if let Err(error) = machine.compute() {
+        println!("{}: {}", path, error);
+    }
+}
+
+fn trace(args: &[String]) {
+    let usage = "Usage: trace <program> <output> [--csv] [--addresses <start>..<end>] [--opcodes <op,...>]";
+    let (path, destination) = match args {
+        [path, destination, ..] => (path, destination),
+        _ => panic!("{}", usage)
+    };
+    let mut filter = trace::TraceFilter::new();
+    let mut csv = false;
+    let mut flags = args[2..].iter();
+
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "--csv" => csv = true,
+            "--addresses" => {
+                let range = flags.next().expect(usage);
+                let bounds: Vec<usize> = range.split("..").map(|bound| bound.parse().expect(usage)).collect();
+                filter = filter.addresses(bounds[0]..bounds[1]);
+            },
+            "--opcodes" => {
+                let opcodes = flags.next().expect(usage);
+                let opcodes: Vec<i32> = opcodes.split(',').map(|opcode| opcode.parse().expect(usage)).collect();
+                filter = filter.opcodes(&opcodes);
+            },
+            _ => panic!("{}", usage)
+        }
+    }
+
+    let program = load_intcode_program::<i64>(path);
+    let writer = std::io::BufWriter::new(File::create(destination).expect("Unable to create trace file."));
+    let tracer: Box<dyn trace::Tracer<i64> + Send> = if csv {
+        Box::new(trace::CsvTracer::new(writer))
+    } else {
+        Box::new(trace::JsonLinesTracer::new(writer))
+    };
+    let mut machine = computer::Machine::from_program(
+        &program,
+        streams::TextInput::new(std::io::stdin().lock()),
+        streams::TextOutput::new(std::io::stdout())
+    );
+
+    machine.set_tracer(tracer, filter);
+    if let Err(error) = machine.compute() {
+        println!("{}: {}", path, error);
+    }
+}
+
+// Prints a Rust module for the program, reading the words at the comma separated --dynamic addresses from memory.
+fn transpile(args: &[String]) {
+    let usage = "Usage: transpile <program> [--dynamic <addresses>]";
+    let path = args.first().expect(usage);
+    let program = load_intcode_program::<i64>(path);
+    let dynamic: Vec<usize> = match args.get(1..) {
+        Some([flag, addresses]) if flag == "--dynamic" =>
+            addresses.split(',').map(|address| address.parse().expect("Addresses must be numbers.")).collect(),
+        Some([]) => vec![],
+        _ => panic!("{}", usage)
+    };
+
+    print!("{}", transpiler::transpile(program.image(), &dynamic));
 }
 
 fn load_day_one_data() -> impl Iterator<Item = i32> {
@@ -24,16 +388,19 @@ fn load_day_one_data() -> impl Iterator<Item = i32> {
     })
 }
 
-fn load_day_two_data() -> Vec<i32> {
-    let file = File::open("inputs/two.txt").unwrap();
+fn load_intcode_program<W: computer::Word>(path: &str) -> program::Program<W> {
+    let file = File::open(path).unwrap();
     let mut buf_reader = BufReader::new(file);
     let mut data_line = String::new();
-    buf_reader.read_line(&mut data_line).expect("Unable to read day 2 input.");
-    data_line.trim().split(',').map(|line| {
-        line.parse::<i32>()
-    }).filter(|result| result.is_ok()).map(|result| result.unwrap()).collect()
+    buf_reader.read_line(&mut data_line).expect("Unable to read Intcode input.");
+    data_line.parse().unwrap()
 }
 
+fn load_day_two_data<W: computer::Word>() -> Vec<W> {
+    load_intcode_program("inputs/two.txt").image().to_vec()
+}
+
+#[allow(clippy::result_filter_map)]
 fn load_day_three_data() -> Vec<Vec<three::WireVector>> {
     let file = File::open("inputs/three.txt").unwrap();
     let buf_reader = BufReader::new(file);
@@ -47,14 +414,8 @@ fn load_day_three_data() -> Vec<Vec<three::WireVector>> {
     }).collect()
 }
 
-fn load_day_five_data() -> Vec<i32> {
-    let file = File::open("inputs/five.txt").unwrap();
-    let mut buf_reader = BufReader::new(file);
-    let mut data_line = String::new();
-    buf_reader.read_line(&mut data_line).expect("Unable to read day 5 input.");
-    data_line.trim().split(',').map(|line| {
-        line.parse::<i32>()
-    }).filter(|result| result.is_ok()).map(|result| result.unwrap()).collect()
+fn load_day_five_data<W: computer::Word>() -> Vec<W> {
+    load_intcode_program("inputs/five.txt").image().to_vec()
 }
 
 fn load_day_six_data() -> Vec<six::Orbit> {
@@ -79,7 +440,7 @@ fn solve_one_part_two() {
 
 #[allow(dead_code)]
 fn solve_two_part_one() {
-    let mut data = load_day_two_data();
+    let mut data = load_day_two_data::<i64>();
     data[1] = 12;
     data[2] = 2;
     println!("Day 2 Part 1 Solution: {}", two::solve_part_one(&mut data));
@@ -87,8 +448,8 @@ fn solve_two_part_one() {
 
 #[allow(dead_code)]
 fn solve_two_part_two() {
-    let data = load_day_two_data();
-    println!("Day 2 Part 2 Solution: {}", two::solve_part_two(data, 19_690_720));
+    let data = load_day_two_data::<i64>();
+    println!("Day 2 Part 2 Solution: {}", two::solve_part_two_native(data, 19_690_720));
 }
 
 #[allow(dead_code)]
@@ -115,8 +476,10 @@ fn solve_four_part_two() {
 
 #[allow(dead_code)]
 fn solve_five() {
-    let mut data = load_day_five_data();
-    five::solve_part_one(&mut data);
+    let mut data = load_day_five_data::<i64>();
+    if let Err(error) = five::solve_part_one(&mut data) {
+        println!("Day 5 Failed: {}", error);
+    }
 }
 
 #[allow(dead_code)]
@@ -0,0 +1,222 @@
+//! Intcode memory which extends past the loaded program image.
+use crate::computer::Word;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Writes this far past the end of the image are stored densely, anything higher is sparse.
+const DENSE_LIMIT: usize = 1 << 16;
+
+// Shared images are copied into an owned buffer on their first write.
+enum Image<'a, W: Word> {
+    Borrowed(&'a mut [W]),
+    Shared(Arc<[W]>),
+    Owned(Vec<W>)
+}
+
+impl<'a, W: Word> Image<'a, W> {
+    fn as_slice(&self) -> &[W] {
+        match self {
+            Image::Borrowed(image) => image,
+            Image::Shared(image) => image,
+            Image::Owned(image) => image
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [W] {
+        if let Image::Shared(image) = self {
+            *self = Image::Owned(image.to_vec());
+        }
+
+        match self {
+            Image::Borrowed(image) => image,
+            Image::Owned(image) => image,
+            Image::Shared(_) => unreachable!()
+        }
+    }
+}
+
+pub struct Memory<'a, W: Word> {
+    image: Image<'a, W>,
+    extension: Vec<W>,
+    // Which words of `extension` have been written, rather than filled in to reach a later one.
+    written: Vec<bool>,
+    sparse: HashMap<usize, W>
+}
+
+impl<'a, W: Word> Memory<'a, W> {
+
+    pub fn new(image: &'a mut [W]) -> Self {
+        Memory::from_image(Image::Borrowed(image))
+    }
+
+    pub fn shared(image: Arc<[W]>) -> Self {
+        Memory::from_image(Image::Shared(image))
+    }
+
+    pub fn owned(image: Vec<W>) -> Self {
+        Memory::from_image(Image::Owned(image))
+    }
+
+    fn from_image(image: Image<'a, W>) -> Self {
+        Memory {
+            image,
+            extension: vec![],
+            written: vec![],
+            sparse: HashMap::new()
+        }
+    }
+
+    pub fn image_len(&self) -> usize {
+        self.image.as_slice().len()
+    }
+
+    pub fn image(&self) -> &[W] {
+        self.image.as_slice()
+    }
+
+    /// Every non-zero word past the image, in address order.
+    pub fn extension(&self) -> Vec<(usize, W)> {
+        let image_len = self.image_len();
+        let mut words: Vec<(usize, W)> = self.extension.iter().enumerate()
+            .map(|(offset, value)| (image_len + offset, *value))
+            .chain(self.sparse.iter().map(|(address, value)| (*address, *value)))
+            .filter(|(_, value)| *value != W::from(0))
+            .collect();
+
+        words.sort_by_key(|(address, _)| *address);
+        words
+    }
+
+    /// Whether `address` is part of the image or has been written since.
+    pub fn written(&self, address: usize) -> bool {
+        let image_len = self.image_len();
+        if address < image_len {
+            return true;
+        }
+
+        let offset = address - image_len;
+        if offset < DENSE_LIMIT {
+            self.written.get(offset) == Some(&true)
+        } else {
+            self.sparse.contains_key(&address)
+        }
+    }
+
+    pub fn read(&self, address: usize) -> W {
+        let image = self.image.as_slice();
+        if address < image.len() {
+            return image[address];
+        }
+
+        let offset = address - image.len();
+        if offset < DENSE_LIMIT {
+            return match self.extension.get(offset) {
+                Some(value) => *value,
+                None => W::from(0)
+            };
+        }
+
+        match self.sparse.get(&address) {
+            Some(value) => *value,
+            None => W::from(0)
+        }
+    }
+
+    pub fn write(&mut self, address: usize, value: W) {
+        let image_len = self.image_len();
+        if address < image_len {
+            self.image.as_mut_slice()[address] = value;
+            return;
+        }
+
+        let offset = address - image_len;
+        if offset < DENSE_LIMIT {
+            if offset >= self.extension.len() {
+                self.extension.resize(offset + 1, W::from(0));
+                self.written.resize(offset + 1, false);
+            }
+            self.extension[offset] = value;
+            self.written[offset] = true;
+        } else {
+            self.sparse.insert(address, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Memory;
+    use std::sync::Arc;
+
+    #[test]
+    fn reads_past_image_as_zero() {
+        let mut image = [1, 2, 3];
+        let memory = Memory::new(&mut image);
+
+        assert_eq!(memory.read(2), 3);
+        assert_eq!(memory.read(3), 0);
+        assert_eq!(memory.read(1_000_000_000), 0);
+    }
+
+    #[test]
+    fn writes_grow_memory() {
+        let mut image = [1, 2, 3];
+        let mut memory = Memory::new(&mut image);
+
+        memory.write(10, 7);
+
+        assert_eq!(memory.read(10), 7);
+        assert_eq!(memory.read(9), 0);
+        assert_eq!(memory.extension.len(), 8);
+        assert!(memory.written(2) && memory.written(10));
+        assert!(!memory.written(9) && !memory.written(11));
+    }
+
+    #[test]
+    fn high_writes_are_sparse() {
+        let mut image = [1, 2, 3];
+        let mut memory = Memory::new(&mut image);
+
+        memory.write(1_000_000_000, 5);
+
+        assert_eq!(memory.read(1_000_000_000), 5);
+        assert!(memory.extension.is_empty());
+    }
+
+    #[test]
+    fn writes_within_image_reach_backing_slice() {
+        let mut image = [1, 2, 3];
+        {
+            let mut memory = Memory::new(&mut image);
+            memory.write(0, 9);
+        }
+
+        assert_eq!(image, [9, 2, 3]);
+    }
+
+    #[test]
+    fn extension_lists_written_words() {
+        let mut image = [1, 2, 3];
+        let mut memory = Memory::new(&mut image);
+
+        memory.write(1_000_000_000, 5);
+        memory.write(4, 7);
+        memory.write(8, 0);
+
+        assert_eq!(memory.extension(), vec![(4, 7), (1_000_000_000, 5)]);
+    }
+
+    #[test]
+    fn shared_image_is_copied_on_write() {
+        let image: Arc<[i64]> = Arc::from(vec![1, 2, 3]);
+        let mut memory = Memory::shared(image.clone());
+
+        assert_eq!(Arc::strong_count(&image), 2);
+
+        memory.write(1, 7);
+
+        assert_eq!(Arc::strong_count(&image), 1);
+        assert_eq!(memory.read(1), 7);
+        assert_eq!(image[1], 2);
+    }
+}
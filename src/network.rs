@@ -0,0 +1,233 @@
+//! Packet switched network of Intcode machines watched over by a NAT, as in problem 23.
+use crate::computer::{ComputerError, Machine, State, Word};
+use crate::program::Program;
+use std::collections::VecDeque;
+use std::fmt;
+
+pub const NAT_ADDRESS: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Packet<W: Word> {
+    pub x: W,
+    pub y: W
+}
+
+#[derive(Debug, PartialEq)]
+pub struct NatReport<W: Word> {
+    /// The first packet any machine sent to the NAT.
+    pub first_packet: Packet<W>,
+    /// The first `y` the NAT delivered to machine 0 twice in a row.
+    pub repeated_wake: W
+}
+
+#[derive(Debug)]
+pub enum NetworkError {
+    Computer { machine: usize, error: ComputerError },
+    UnknownAddress { machine: usize, destination: i128 },
+    Halted,
+    Deadlock
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Computer { machine, error } => write!(f, "Machine {} failed: {}", machine, error),
+            NetworkError::UnknownAddress { machine, destination } =>
+                write!(f, "Machine {} sent a packet to unknown address {}", machine, destination),
+            NetworkError::Halted => write!(f, "Every machine on the network halted."),
+            NetworkError::Deadlock => write!(f, "The network went idle before the NAT received a packet.")
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+pub struct Network<W: Word> {
+    machines: Vec<Machine<W, VecDeque<W>, VecDeque<W>>>,
+    queues: Vec<VecDeque<Packet<W>>>,
+    // Words of a packet a machine has only partly written.
+    partial: Vec<Vec<W>>,
+    nat: Option<Packet<W>>,
+    first_packet: Option<Packet<W>>
+}
+
+impl<W: Word> Network<W> {
+
+    /// Boots `size` machines running `program`, each given its network address as first input.
+    pub fn new(program: &Program<W>, size: usize) -> Self {
+        let machines = (0..size).map(|address| {
+            let mut machine = Machine::from_program(program, VecDeque::new(), VecDeque::new());
+            machine.provide_input(W::from(address as i32));
+            machine
+        }).collect();
+
+        Network {
+            machines,
+            queues: vec![VecDeque::new(); size],
+            partial: vec![vec![]; size],
+            nat: None,
+            first_packet: None
+        }
+    }
+
+    fn route(&mut self, machine: usize, destination: W, packet: Packet<W>) -> Result<(), NetworkError> {
+        match destination.to_address() {
+            Some(NAT_ADDRESS) => {
+                self.first_packet.get_or_insert(packet);
+                self.nat = Some(packet);
+                Ok(())
+            },
+            Some(address) if address < self.queues.len() => {
+                self.queues[address].push_back(packet);
+                Ok(())
+            },
+            _ => Err(NetworkError::UnknownAddress { machine, destination: destination.into() })
+        }
+    }
+
+    // Runs a machine until it reads input, which never blocks: an empty queue supplies -1.
+    // Returns whether the machine sent a packet and whether it found its queue empty.
+    fn run_machine(&mut self, machine: usize) -> Result<(bool, bool), NetworkError> {
+        let mut sent = false;
+
+        loop {
+            let state = self.machines[machine].run_until_io()
+                .map_err(|error| NetworkError::Computer { machine, error })?;
+
+            match state {
+                State::Output(value) => {
+                    self.partial[machine].push(value);
+
+                    if let [destination, x, y] = self.partial[machine][..] {
+                        self.partial[machine].clear();
+                        self.route(machine, destination, Packet { x, y })?;
+                        sent = true;
+                    }
+                },
+                State::NeedsInput => match self.queues[machine].pop_front() {
+                    Some(packet) => {
+                        self.machines[machine].provide_input(packet.x);
+                        self.machines[machine].provide_input(packet.y);
+                        return Ok((sent, false));
+                    },
+                    None => {
+                        self.machines[machine].provide_input(W::from(-1));
+                        return Ok((sent, true));
+                    }
+                },
+                State::Terminated(_) => return Ok((sent, true)),
+                State::Running | State::Paused => ()
+            }
+        }
+    }
+
+    /// Schedules the machines round robin until the NAT wakes machine 0 with the same `y` twice.
+    pub fn run(&mut self) -> Result<NatReport<W>, NetworkError> {
+        let mut last_wake: Option<W> = None;
+
+        loop {
+            let mut active = false;
+
+            for machine in 0..self.machines.len() {
+                let (sent, idle) = self.run_machine(machine)?;
+                active |= sent || !idle;
+            }
+
+            if self.machines.iter().all(|machine| machine.termination().is_some()) {
+                return Err(NetworkError::Halted);
+            }
+
+            // Packets queued for halted machines are never read, so they don't keep the network busy.
+            let waiting = self.queues.iter().zip(&self.machines)
+                .any(|(queue, machine)| !queue.is_empty() && machine.termination().is_none());
+
+            if active || waiting {
+                continue;
+            }
+
+            let packet = self.nat.ok_or(NetworkError::Deadlock)?;
+
+            if last_wake == Some(packet.y) {
+                return Ok(NatReport {
+                    first_packet: self.first_packet.unwrap_or(packet),
+                    repeated_wake: packet.y
+                });
+            }
+
+            last_wake = Some(packet.y);
+            self.queues[0].push_back(packet);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NatReport, Network, NetworkError, Packet};
+    use crate::assembler::assemble;
+    use crate::program::Program;
+
+    // Machine 0 starts a packet down the line, every machine adds one to `y` up to 7 and passes it
+    // on, and the last machine hands it to the NAT.
+    fn relay(size: usize) -> Program<i64> {
+        let source = format!("
+                   in   [address]
+                   jnz  [address], #receive
+                   out  #1
+                   out  #3
+                   out  #0
+            receive:
+                   in   [x]
+                   eq   [x], #-1, [test]
+                   jnz  [test], #receive
+                   in   [y]
+                   lt   [y], #7, [test]
+                   add  [y], [test], [y]
+                   add  [address], #1, [destination]
+                   eq   [destination], #{}, [test]
+                   jz   [test], #send
+                   add  #255, #0, [destination]
+            send:  out  [destination]
+                   out  [x]
+                   out  [y]
+                   jz   #0, #receive
+            address: data 0
+            destination: data 0
+            x: data 0
+            y: data 0
+            test: data 0
+        ", size);
+
+        Program::new(assemble(&source).unwrap())
+    }
+
+    #[test]
+    fn nat_wakes_network() {
+        assert_eq!(Network::new(&relay(5), 5).run().unwrap(), NatReport {
+            first_packet: Packet { x: 3, y: 4 },
+            repeated_wake: 7
+        });
+    }
+
+    #[test]
+    fn fifty_machines() {
+        assert_eq!(Network::new(&relay(50), 50).run().unwrap(), NatReport {
+            first_packet: Packet { x: 3, y: 7 },
+            repeated_wake: 7
+        });
+    }
+
+    #[test]
+    fn failures() {
+        let silent: Program<i64> = "3,10,3,10,1105,1,2".parse().unwrap();
+        assert!(matches!(Network::new(&silent, 3).run(), Err(NetworkError::Deadlock)));
+
+        let halting: Program<i64> = "3,10,99".parse().unwrap();
+        assert!(matches!(Network::new(&halting, 3).run(), Err(NetworkError::Halted)));
+
+        let misrouting: Program<i64> = "3,10,104,7,104,1,104,2,99".parse().unwrap();
+        assert!(matches!(
+            Network::new(&misrouting, 3).run(),
+            Err(NetworkError::UnknownAddress { machine: 0, destination: 7 })
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+//! Execution profiles of opcodes, addresses, jumps and loops.
+use crate::computer::OpCode;
+use crate::disassembler::mnemonic;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct JumpCounts {
+    pub taken: u64,
+    pub not_taken: u64
+}
+
+/// A taken jump back to an earlier address, closing a loop over `start..=end`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HotLoop {
+    pub start: usize,
+    pub end: usize,
+    pub iterations: u64
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub instructions: u64,
+    // Keyed by opcode number, since `OpCode` can't be hashed or ordered.
+    opcodes: BTreeMap<i32, (&'static str, u64)>,
+    addresses: HashMap<usize, u64>,
+    jumps: HashMap<usize, JumpCounts>,
+    back_edges: HashMap<(usize, usize), u64>
+}
+
+fn ranked<K: Ord + Copy>(counts: impl Iterator<Item = (K, u64)>) -> Vec<(K, u64)> {
+    let mut counts: Vec<(K, u64)> = counts.collect();
+    counts.sort_by(|(key, count), (other_key, other_count)| other_count.cmp(count).then(key.cmp(other_key)));
+    counts
+}
+
+impl Profile {
+
+    pub fn new() -> Self {
+        Profile::default()
+    }
+
+    /// Records one executed instruction and the address execution continued from.
+    pub fn record(&mut self, address: usize, opcode: &OpCode, next_address: usize) {
+        self.instructions += 1;
+        self.opcodes.entry(opcode.code()).or_insert((mnemonic(opcode), 0)).1 += 1;
+        *self.addresses.entry(address).or_insert(0) += 1;
+
+        if let OpCode::JumpIfTrue | OpCode::JumpIfFalse = opcode {
+            let counts = self.jumps.entry(address).or_default();
+
+            if next_address == address + opcode.instruction_size() {
+                counts.not_taken += 1;
+            } else {
+                counts.taken += 1;
+
+                if next_address <= address {
+                    *self.back_edges.entry((next_address, address)).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    pub fn opcodes(&self) -> Vec<(&'static str, u64)> {
+        ranked(self.opcodes.values().copied())
+    }
+
+    pub fn addresses(&self) -> Vec<(usize, u64)> {
+        ranked(self.addresses.iter().map(|(address, count)| (*address, *count)))
+    }
+
+    pub fn jumps(&self) -> Vec<(usize, JumpCounts)> {
+        let mut jumps: Vec<(usize, JumpCounts)> = self.jumps.iter().map(|(address, counts)| (*address, *counts)).collect();
+        jumps.sort_by(|(address, counts), (other_address, other_counts)| {
+            (other_counts.taken + other_counts.not_taken).cmp(&(counts.taken + counts.not_taken)).then(address.cmp(other_address))
+        });
+        jumps
+    }
+
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        ranked(self.back_edges.iter().map(|(edge, iterations)| (*edge, *iterations))).into_iter()
+            .map(|((start, end), iterations)| HotLoop { start, end, iterations })
+            .collect()
+    }
+
+    fn percentage(&self, count: u64) -> f64 {
+        if self.instructions == 0 {
+            return 0.0;
+        }
+
+        count as f64 * 100.0 / self.instructions as f64
+    }
+
+    /// Writes a ranked report, listing at most `limit` addresses.
+    pub fn report<Wr: Write>(&self, mut writer: Wr, limit: usize) -> io::Result<()> {
+        writeln!(writer, "Instructions executed: {}", self.instructions)?;
+
+        writeln!(writer, "\nOpcodes:")?;
+        for (name, count) in self.opcodes() {
+            writeln!(writer, "{:<8}{:>10}{:>8.1}%", name, count, self.percentage(count))?;
+        }
+
+        writeln!(writer, "\nHot addresses:")?;
+        for (address, count) in self.addresses().into_iter().take(limit) {
+            writeln!(writer, "{:04}    {:>10}{:>8.1}%", address, count, self.percentage(count))?;
+        }
+
+        writeln!(writer, "\nJumps:")?;
+        for (address, counts) in self.jumps() {
+            writeln!(writer, "{:04}    taken {}, not taken {}", address, counts.taken, counts.not_taken)?;
+        }
+
+        writeln!(writer, "\nHot loops:")?;
+        for hot_loop in self.hot_loops() {
+            writeln!(writer, "{:04}-{:04}  {} iterations", hot_loop.start, hot_loop.end, hot_loop.iterations)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_csv<Wr: Write>(&self, mut writer: Wr) -> io::Result<()> {
+        writeln!(writer, "kind,key,count")?;
+
+        for (name, count) in self.opcodes() {
+            writeln!(writer, "opcode,{},{}", name, count)?;
+        }
+        for (address, count) in self.addresses() {
+            writeln!(writer, "address,{},{}", address, count)?;
+        }
+        for (address, counts) in self.jumps() {
+            writeln!(writer, "jump_taken,{},{}", address, counts.taken)?;
+            writeln!(writer, "jump_not_taken,{},{}", address, counts.not_taken)?;
+        }
+        for hot_loop in self.hot_loops() {
+            writeln!(writer, "loop,{}-{},{}", hot_loop.start, hot_loop.end, hot_loop.iterations)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HotLoop, JumpCounts, Profile};
+    use crate::computer::Machine;
+    use crate::program::Program;
+    use std::collections::VecDeque;
+
+    // Counts memory location 9 down from 3 to 0.
+    fn countdown() -> Profile {
+        let program: Program<i64> = "1001,9,-1,9,1005,9,0,99,0,3".parse().unwrap();
+        let mut machine = Machine::from_program(&program, VecDeque::new(), Vec::new());
+
+        machine.enable_profiling();
+        machine.compute().unwrap();
+
+        machine.take_profile().unwrap()
+    }
+
+    #[test]
+    fn counts() {
+        let profile = countdown();
+
+        assert_eq!(profile.instructions, 7);
+        assert_eq!(profile.opcodes(), vec![("ADD", 3), ("JNZ", 3), ("HLT", 1)]);
+        assert_eq!(profile.addresses(), vec![(0, 3), (4, 3), (7, 1)]);
+        assert_eq!(profile.jumps(), vec![(4, JumpCounts { taken: 2, not_taken: 1 })]);
+        assert_eq!(profile.hot_loops(), vec![HotLoop { start: 0, end: 4, iterations: 2 }]);
+    }
+
+    #[test]
+    fn report() {
+        let mut report: Vec<u8> = vec![];
+        countdown().report(&mut report, 2).unwrap();
+
+        assert_eq!(std::str::from_utf8(&report).unwrap(), "\
+Instructions executed: 7
+
+Opcodes:
+ADD              3    42.9%
+JNZ              3    42.9%
+HLT              1    14.3%
+
+Hot addresses:
+0000             3    42.9%
+0004             3    42.9%
+
+Jumps:
+0004    taken 2, not taken 1
+
+Hot loops:
+0000-0004  2 iterations
+");
+    }
+
+    #[test]
+    fn csv() {
+        let mut csv: Vec<u8> = vec![];
+        countdown().write_csv(&mut csv).unwrap();
+
+        assert_eq!(std::str::from_utf8(&csv).unwrap(), "\
+kind,key,count
+opcode,ADD,3
+opcode,JNZ,3
+opcode,HLT,1
+address,0,3
+address,4,3
+address,7,1
+jump_taken,4,2
+jump_not_taken,4,1
+loop,0-4,2
+");
+    }
+
+    #[test]
+    fn off_by_default() {
+        let program: Program<i64> = "99".parse().unwrap();
+        let mut machine = Machine::from_program(&program, VecDeque::new(), Vec::new());
+        machine.compute().unwrap();
+
+        assert!(machine.take_profile().is_none());
+    }
+}
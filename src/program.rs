@@ -0,0 +1,77 @@
+//! Immutable Intcode program images shared between machines.
+use crate::computer::Word;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq)]
+pub struct ParseProgramError {
+    pub position: usize,
+    pub literal: String
+}
+
+impl fmt::Display for ParseProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unable to parse word {}: [{}]", self.position, self.literal)
+    }
+}
+
+impl std::error::Error for ParseProgramError {}
+
+#[derive(Debug, Clone)]
+pub struct Program<W: Word> {
+    image: Arc<[W]>
+}
+
+impl<W: Word> Program<W> {
+
+    pub fn new(image: Vec<W>) -> Self {
+        Program {
+            image: Arc::from(image)
+        }
+    }
+
+    pub fn image(&self) -> &[W] {
+        &self.image
+    }
+
+    pub fn shared_image(&self) -> Arc<[W]> {
+        self.image.clone()
+    }
+}
+
+impl<W: Word> FromStr for Program<W> {
+    type Err = ParseProgramError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut image = vec![];
+
+        for (position, literal) in source.trim().split(',').enumerate() {
+            match literal.trim().parse::<W>() {
+                Ok(value) => image.push(value),
+                Err(_) => return Err(ParseProgramError { position, literal: literal.to_owned() })
+            }
+        }
+
+        Ok(Program::new(image))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParseProgramError, Program};
+
+    #[test]
+    fn parse() {
+        let program: Program<i64> = "1,0,0,3,99\n".parse().unwrap();
+
+        assert_eq!(program.image(), &[1, 0, 0, 3, 99]);
+    }
+
+    #[test]
+    fn parse_failure() {
+        let program = "1,0,x,3,99".parse::<Program<i64>>();
+
+        assert_eq!(program.unwrap_err(), ParseProgramError { position: 2, literal: "x".to_owned() });
+    }
+}
@@ -0,0 +1,45 @@
+//! A small seeded random number generator, for fuzzing and the random device.
+
+/// A xorshift64* generator, so every run can be reproduced from its seed.
+#[derive(Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+
+    pub fn new(seed: u64) -> Self {
+        // Xorshift never leaves a zero state.
+        Rng(seed.max(1))
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + self.below((high - low + 1) as u64) as i64
+    }
+
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rng;
+
+    #[test]
+    fn reproducible() {
+        let (mut first, mut second) = (Rng::new(7), Rng::new(7));
+
+        assert_eq!((0..5).map(|_| first.next()).collect::<Vec<u64>>(), (0..5).map(|_| second.next()).collect::<Vec<u64>>());
+        assert!((0..1000).all(|_| (-2..=2).contains(&first.range(-2, 2))));
+    }
+}
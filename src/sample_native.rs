@@ -0,0 +1,110 @@
+//! Generated by `transpile` from a 38 word Intcode image. Regenerate rather than edit.
+#![allow(clippy::all, unreachable_code, unused_assignments, unused_mut)]
+use crate::computer::{ComputerError, Termination};
+use crate::streams::{Input, Output};
+use crate::transpiler::Native;
+
+pub static IMAGE: [i64; 38] = [
+    3, 35, 109, 100, 21002, 35, 2, 0, 204, 0, 1001, 35, 1, 35, 1007, 35,
+    5, 36, 1005, 36, 4, 5, 35, 37, 1007, 35, 7, 36, 1002, 36, 4, 32,
+    4, 35, 99, 0, 0, 24,
+];
+
+// Word ranges compiled below, and words read from memory on each use instead.
+const CODE: &[(usize, usize)] = &[(0, 35)];
+const DYNAMIC: &[usize] = &[];
+
+/// Behaves like `Computer::new(memory, source, sink).compute()`, without step limits or tracing.
+pub fn run<In: Input<i64>, Out: Output<i64>>(memory: &mut [i64], source: In, sink: Out) -> Result<Termination, ComputerError> {
+    let mut native = Native::new(memory, &IMAGE, CODE, DYNAMIC, source, sink);
+    if !native.compatible() {
+        return native.interpret(0);
+    }
+
+    let mut address = 0;
+    loop {
+        address = match address {
+            0 => {
+                if native.changed(0, 4) {
+                    return native.interpret(0);
+                }
+                // 0000: IN   [35]
+                let target = 35;
+                let value = native.input()?;
+                native.store(target, value);
+                if native.changed(2, 4) {
+                    return native.interpret(2);
+                }
+                // 0002: ARB  #100
+                native.adjust(2, 100)?;
+                4
+            },
+            4 => {
+                if native.changed(4, 21) {
+                    return native.interpret(4);
+                }
+                // 0004: MUL  [35], #2, [rb+0]
+                let (left, right) = (native.load(4, 35)?, 2);
+                let target = native.target(4, native.relative(4, 0)?)?;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 4 })?;
+                native.store(target, value);
+                if native.changed(8, 21) {
+                    return native.interpret(8);
+                }
+                // 0008: OUT  [rb+0]
+                native.output(native.load(8, native.relative(8, 0)?)?)?;
+                // 0010: ADD  [35], #1, [35]
+                let (left, right) = (native.load(10, 35)?, 1);
+                let target = 35;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 10 })?;
+                native.store(target, value);
+                if native.changed(14, 21) {
+                    return native.interpret(14);
+                }
+                // 0014: LT   [35], #5, [36]
+                let (left, right) = (native.load(14, 35)?, 5);
+                let target = 36;
+                let value = (left < right) as i64;
+                native.store(target, value);
+                if native.changed(18, 21) {
+                    return native.interpret(18);
+                }
+                // 0018: JNZ  [36], #4
+                if native.load(18, 36)? != 0 { 4 } else { 21 }
+            },
+            21 => {
+                if native.changed(21, 24) {
+                    return native.interpret(21);
+                }
+                // 0021: JNZ  [35], [37]
+                if native.load(21, 35)? != 0 { native.target(21, native.load(21, 37)?)? } else { 24 }
+            },
+            24 => {
+                if native.changed(24, 35) {
+                    return native.interpret(24);
+                }
+                // 0024: LT   [35], #7, [36]
+                let (left, right) = (native.load(24, 35)?, 7);
+                let target = 36;
+                let value = (left < right) as i64;
+                native.store(target, value);
+                if native.changed(28, 35) {
+                    return native.interpret(28);
+                }
+                // 0028: MUL  [36], #4, [32]
+                let (left, right) = (native.load(28, 36)?, 4);
+                let target = 32;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 28 })?;
+                native.store(target, value);
+                if native.changed(32, 35) {
+                    return native.interpret(32);
+                }
+                // 0032: OUT  [35]
+                native.output(native.load(32, 35)?)?;
+                // 0034: HLT
+                return Ok(Termination::Halted);
+            },
+            address => return native.interpret(address)
+        };
+    }
+}
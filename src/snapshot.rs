@@ -0,0 +1,241 @@
+//! Versioned text snapshots of complete machine state.
+//!
+//! A snapshot is a header line followed by one `key: value` line per field:
+//!
+//! ```text
+//! intcode-snapshot 1
+//! address: 4
+//! relative-base: 0
+//! status: running
+//! input: 7,8
+//! image: 3,9,4,9,99
+//! memory: 1000=5
+//! ```
+//!
+//! `memory` lists the non-zero words written past the image as `address=value` pairs.
+use crate::computer::{Termination, Word};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+pub const VERSION: u32 = 1;
+const HEADER: &str = "intcode-snapshot";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Version(String),
+    MissingField(&'static str),
+    InvalidField { field: &'static str, value: String }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "Unable to access snapshot: {}", error),
+            SnapshotError::Version(header) => write!(f, "Unsupported snapshot version: [{}]", header),
+            SnapshotError::MissingField(field) => write!(f, "Snapshot is missing field [{}]", field),
+            SnapshotError::InvalidField { field, value } => write!(f, "Invalid snapshot {}: [{}]", field, value)
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot<W: Word> {
+    pub address: usize,
+    pub relative_base: W,
+    pub termination: Option<Termination>,
+    pub input: Vec<W>,
+    pub image: Vec<W>,
+    pub memory: Vec<(usize, W)>
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values.iter().map(T::to_string).collect::<Vec<String>>().join(",")
+}
+
+fn parse<T: std::str::FromStr>(field: &'static str, value: &str) -> Result<T, SnapshotError> {
+    value.parse().map_err(|_| SnapshotError::InvalidField { field, value: value.to_owned() })
+}
+
+fn parse_list<T: std::str::FromStr>(field: &'static str, value: &str) -> Result<Vec<T>, SnapshotError> {
+    value.split(',').filter(|word| !word.is_empty()).map(|word| parse(field, word)).collect()
+}
+
+impl<W: Word> Snapshot<W> {
+
+    pub fn save<Wr: Write>(&self, mut writer: Wr) -> io::Result<()> {
+        let status = match self.termination {
+            None => "running",
+            Some(Termination::Halted) => "halted",
+            Some(Termination::RanOffEnd) => "ran-off-end"
+        };
+        let memory: Vec<String> = self.memory.iter().map(|(address, value)| format!("{}={}", address, value)).collect();
+
+        writeln!(writer, "{} {}", HEADER, VERSION)?;
+        writeln!(writer, "address: {}", self.address)?;
+        writeln!(writer, "relative-base: {}", self.relative_base)?;
+        writeln!(writer, "status: {}", status)?;
+        writeln!(writer, "input: {}", join(&self.input))?;
+        writeln!(writer, "image: {}", join(&self.image))?;
+        writeln!(writer, "memory: {}", memory.join(","))?;
+        writer.flush()
+    }
+
+    pub fn load<R: BufRead>(reader: R) -> Result<Self, SnapshotError> {
+        let mut lines = reader.lines();
+
+        let header = lines.next().transpose().map_err(SnapshotError::Io)?.unwrap_or_default();
+        if header.trim() != format!("{} {}", HEADER, VERSION) {
+            return Err(SnapshotError::Version(header.trim().to_owned()));
+        }
+
+        let mut fields = std::collections::HashMap::new();
+        for line in lines {
+            let line = line.map_err(SnapshotError::Io)?;
+            if let Some((key, value)) = line.split_once(':') {
+                fields.insert(key.trim().to_owned(), value.trim().to_owned());
+            }
+        }
+
+        let field = |name: &'static str| fields.get(name).map(String::as_str).ok_or(SnapshotError::MissingField(name));
+
+        let termination = match field("status")? {
+            "running" => None,
+            "halted" => Some(Termination::Halted),
+            "ran-off-end" => Some(Termination::RanOffEnd),
+            status => return Err(SnapshotError::InvalidField { field: "status", value: status.to_owned() })
+        };
+        let memory = field("memory")?.split(',').filter(|pair| !pair.is_empty()).map(|pair| {
+            match pair.split_once('=') {
+                Some((address, value)) => Ok((parse("memory", address)?, parse("memory", value)?)),
+                None => Err(SnapshotError::InvalidField { field: "memory", value: pair.to_owned() })
+            }
+        }).collect::<Result<Vec<(usize, W)>, SnapshotError>>()?;
+
+        Ok(Snapshot {
+            address: parse("address", field("address")?)?,
+            relative_base: parse("relative-base", field("relative-base")?)?,
+            termination,
+            input: parse_list("input", field("input")?)?,
+            image: parse_list("image", field("image")?)?,
+            memory
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Snapshot, SnapshotError};
+    use crate::computer::{ComputerError, Machine, State, Termination};
+    use crate::program::Program;
+    use crate::streams::{TextInput, TextOutput};
+    use std::collections::VecDeque;
+    use std::io::BufReader;
+
+    fn round_trip(snapshot: &Snapshot<i64>) -> Snapshot<i64> {
+        let mut saved: Vec<u8> = vec![];
+        snapshot.save(&mut saved).unwrap();
+
+        Snapshot::load(BufReader::new(saved.as_slice())).unwrap()
+    }
+
+    #[test]
+    fn format() {
+        let snapshot = Snapshot {
+            address: 4,
+            relative_base: -2,
+            termination: None,
+            input: vec![7, 8],
+            image: vec![3,9,4,9,99],
+            memory: vec![(1000, 5)]
+        };
+        let mut saved: Vec<u8> = vec![];
+        snapshot.save(&mut saved).unwrap();
+
+        assert_eq!(std::str::from_utf8(&saved).unwrap(), "\
+intcode-snapshot 1
+address: 4
+relative-base: -2
+status: running
+input: 7,8
+image: 3,9,4,9,99
+memory: 1000=5
+");
+        assert_eq!(round_trip(&snapshot), snapshot);
+    }
+
+    #[test]
+    fn resumed_quine_matches_uninterrupted_run() {
+        let program: Program<i64> = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99".parse().unwrap();
+
+        let mut uninterrupted: Vec<u8> = vec![];
+        Machine::from_program(&program, VecDeque::new(), TextOutput::new(&mut uninterrupted)).compute().unwrap();
+
+        let mut resumed: Vec<u8> = vec![];
+        let mut machine = Machine::from_program(&program, VecDeque::new(), VecDeque::new());
+        for _ in 0..40 {
+            if let State::Output(value) = machine.step().unwrap() {
+                resumed.extend(format!("{}\n", value).bytes());
+            }
+        }
+
+        let snapshot = round_trip(&machine.snapshot());
+        drop(machine);
+
+        Machine::from_snapshot(&snapshot, VecDeque::new(), TextOutput::new(&mut resumed)).compute().unwrap();
+
+        assert_eq!(resumed, uninterrupted);
+    }
+
+    #[test]
+    fn resumes_after_input_runs_out() {
+        // Sums its inputs into memory far past the image and outputs the running total until it reads 0.
+        let program: Program<i64> = "3,100,1006,100,14,1,100,5000,5000,4,5000,1105,1,0,99".parse().unwrap();
+
+        let mut uninterrupted: Vec<u8> = vec![];
+        Machine::from_program(&program, TextInput::new("4\n5\n6\n0\n".as_bytes()), TextOutput::new(&mut uninterrupted))
+            .compute().unwrap();
+
+        let mut resumed: Vec<u8> = vec![];
+        let mut machine = Machine::from_program(&program, TextInput::new("4\n5\n".as_bytes()), TextOutput::new(&mut resumed));
+        assert!(matches!(machine.compute(), Err(ComputerError::InputEof)));
+
+        let snapshot = round_trip(&machine.snapshot());
+        drop(machine);
+
+        assert_eq!(snapshot.memory, vec![(100, 5), (5000, 9)]);
+
+        let mut machine = Machine::from_snapshot(&snapshot, TextInput::new("6\n0\n".as_bytes()), TextOutput::new(&mut resumed));
+        assert_eq!(machine.compute().unwrap(), Termination::Halted);
+        drop(machine);
+
+        assert_eq!(resumed, uninterrupted);
+    }
+
+    #[test]
+    fn pending_input_and_termination_survive() {
+        let program: Program<i64> = "3,0,99".parse().unwrap();
+        let mut machine = Machine::from_program(&program, VecDeque::new(), Vec::new());
+        machine.provide_input(1);
+        machine.provide_input(2);
+        machine.compute().unwrap();
+
+        let restored = Machine::from_snapshot(&round_trip(&machine.snapshot()), VecDeque::new(), Vec::<i64>::new());
+
+        assert_eq!(restored.termination(), Some(Termination::Halted));
+        assert_eq!(restored.pending_input(), &VecDeque::from(vec![2]));
+        assert_eq!(restored.read_memory(0), 1);
+        assert_eq!(restored.address(), 3);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let loaded = Snapshot::<i64>::load(BufReader::new("intcode-snapshot 2\naddress: 0\n".as_bytes()));
+        assert!(matches!(loaded, Err(SnapshotError::Version(header)) if header == "intcode-snapshot 2"));
+
+        let loaded = Snapshot::<i64>::load(BufReader::new("intcode-snapshot 1\naddress: 0\n".as_bytes()));
+        assert!(matches!(loaded, Err(SnapshotError::MissingField("status"))));
+    }
+}
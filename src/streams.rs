@@ -0,0 +1,321 @@
+//! Word level input and output for the Intcode computer.
+use crate::computer::{ComputerError, Word};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{Receiver, Sender};
+
+pub trait Input<W: Word> {
+    fn read(&mut self) -> Result<W, ComputerError>;
+}
+
+pub trait Output<W: Word> {
+    fn write(&mut self, value: W) -> Result<(), ComputerError>;
+}
+
+impl<W: Word> Input<W> for VecDeque<W> {
+    fn read(&mut self) -> Result<W, ComputerError> {
+        self.pop_front().ok_or(ComputerError::InputEof)
+    }
+}
+
+impl<W: Word> Output<W> for VecDeque<W> {
+    fn write(&mut self, value: W) -> Result<(), ComputerError> {
+        self.push_back(value);
+        Ok(())
+    }
+}
+
+impl<W: Word> Output<W> for Vec<W> {
+    fn write(&mut self, value: W) -> Result<(), ComputerError> {
+        self.push(value);
+        Ok(())
+    }
+}
+
+impl<W: Word, T: Input<W> + ?Sized> Input<W> for &mut T {
+    fn read(&mut self) -> Result<W, ComputerError> {
+        (**self).read()
+    }
+}
+
+impl<W: Word, T: Output<W> + ?Sized> Output<W> for &mut T {
+    fn write(&mut self, value: W) -> Result<(), ComputerError> {
+        (**self).write(value)
+    }
+}
+
+impl<W: Word> Input<W> for Receiver<W> {
+    fn read(&mut self) -> Result<W, ComputerError> {
+        self.recv().map_err(|_| ComputerError::InputEof)
+    }
+}
+
+impl<W: Word> Output<W> for Sender<W> {
+    fn write(&mut self, value: W) -> Result<(), ComputerError> {
+        self.send(value).map_err(|_| {
+            ComputerError::Output(io::Error::new(io::ErrorKind::BrokenPipe, "Output receiver disconnected."))
+        })
+    }
+}
+
+/// Feeds words from any iterator.
+#[allow(dead_code)]
+pub struct Iter<I>(pub I);
+
+impl<W: Word, I: Iterator<Item = W>> Input<W> for Iter<I> {
+    fn read(&mut self) -> Result<W, ComputerError> {
+        self.0.next().ok_or(ComputerError::InputEof)
+    }
+}
+
+/// Reads words from a `FnMut() -> Option<W>` closure or writes them to a `FnMut(W)` closure.
+#[allow(dead_code)]
+pub struct Func<F>(pub F);
+
+impl<W: Word, F: FnMut() -> Option<W>> Input<W> for Func<F> {
+    fn read(&mut self) -> Result<W, ComputerError> {
+        (self.0)().ok_or(ComputerError::InputEof)
+    }
+}
+
+impl<W: Word, F: FnMut(W)> Output<W> for Func<F> {
+    fn write(&mut self, value: W) -> Result<(), ComputerError> {
+        (self.0)(value);
+        Ok(())
+    }
+}
+
+/// Reads one decimal word per line.
+pub struct TextInput<R: BufRead> {
+    reader: R
+}
+
+impl<R: BufRead> TextInput<R> {
+    pub fn new(reader: R) -> Self {
+        TextInput { reader }
+    }
+}
+
+impl<W: Word, R: BufRead> Input<W> for TextInput<R> {
+    fn read(&mut self) -> Result<W, ComputerError> {
+        let mut buffer = String::new();
+        let bytes_read = self.reader.read_line(&mut buffer).map_err(ComputerError::Input)?;
+
+        if bytes_read == 0 {
+            return Err(ComputerError::InputEof);
+        }
+
+        match buffer.trim().parse() {
+            Ok(value) => Ok(value),
+            Err(_) => Err(ComputerError::UnparsableInput(buffer.trim().to_owned()))
+        }
+    }
+}
+
+/// Writes one decimal word per line.
+pub struct TextOutput<Wr: Write> {
+    writer: Wr
+}
+
+impl<Wr: Write> TextOutput<Wr> {
+    pub fn new(writer: Wr) -> Self {
+        TextOutput { writer }
+    }
+}
+
+impl<W: Word, Wr: Write> Output<W> for TextOutput<Wr> {
+    fn write(&mut self, value: W) -> Result<(), ComputerError> {
+        let mut output = value.to_string();
+        output.push('\n');
+        self.writer.write_all(output.as_bytes()).map_err(ComputerError::Output)
+    }
+}
+
+/// Reads lines of text, feeding each character code in turn and ending every line with a newline.
+pub struct AsciiInput<W: Word, R: BufRead> {
+    reader: R,
+    pending: VecDeque<W>
+}
+
+impl<W: Word, R: BufRead> AsciiInput<W, R> {
+    pub fn new(reader: R) -> Self {
+        AsciiInput { reader, pending: VecDeque::new() }
+    }
+}
+
+impl<W: Word, R: BufRead> Input<W> for AsciiInput<W, R> {
+    fn read(&mut self) -> Result<W, ComputerError> {
+        if self.pending.is_empty() {
+            let mut buffer = String::new();
+            if self.reader.read_line(&mut buffer).map_err(ComputerError::Input)? == 0 {
+                return Err(ComputerError::InputEof);
+            }
+
+            let line = buffer.trim_end_matches(&['\r', '\n'][..]);
+            self.pending.extend(line.chars().chain(Some('\n')).map(|character| W::from(character as i32)));
+        }
+
+        Ok(self.pending.pop_front().expect("Every line ends with a newline."))
+    }
+}
+
+/// Writes words below 128 as characters, and anything else as a decimal word on its own line.
+pub struct AsciiOutput<Wr: Write> {
+    writer: Wr,
+    line_start: bool
+}
+
+impl<Wr: Write> AsciiOutput<Wr> {
+    pub fn new(writer: Wr) -> Self {
+        AsciiOutput { writer, line_start: true }
+    }
+}
+
+impl<W: Word, Wr: Write> Output<W> for AsciiOutput<Wr> {
+    fn write(&mut self, value: W) -> Result<(), ComputerError> {
+        let output = match Into::<i128>::into(value) {
+            code @ 0..=127 => (code as u8 as char).to_string(),
+            _ if self.line_start => format!("{}\n", value),
+            _ => format!("\n{}\n", value)
+        };
+        self.line_start = output.ends_with('\n');
+
+        // Flushed every time, so prompts without a newline show up before the program reads.
+        self.writer.write_all(output.as_bytes())
+            .and_then(|_| self.writer.flush())
+            .map_err(ComputerError::Output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsciiInput, AsciiOutput, Func, Input, Iter, Output, TextInput, TextOutput};
+    use crate::computer::{ComputerError, Machine};
+    use crate::program::Program;
+    use std::collections::VecDeque;
+    use std::io::BufReader;
+    use std::sync::mpsc;
+
+    #[test]
+    fn vec_deque() {
+        let mut queue: VecDeque<i64> = VecDeque::new();
+
+        queue.write(3).unwrap();
+        queue.write(4).unwrap();
+
+        assert_eq!(queue.read().unwrap(), 3);
+        assert_eq!(queue.read().unwrap(), 4);
+        assert!(matches!(queue.read(), Err(ComputerError::InputEof)));
+    }
+
+    #[test]
+    fn iterator() {
+        let mut input = Iter(vec![1i64, 2].into_iter());
+
+        assert_eq!(input.read().unwrap(), 1);
+        assert_eq!(input.read().unwrap(), 2);
+        assert!(matches!(input.read(), Err(ComputerError::InputEof)));
+    }
+
+    #[test]
+    fn closures() {
+        let mut next = 0i64;
+        let mut input = Func(|| {
+            next += 1;
+            Some(next)
+        });
+
+        assert_eq!(input.read().unwrap(), 1);
+        assert_eq!(input.read().unwrap(), 2);
+
+        let mut total = 0i64;
+        {
+            let mut output = Func(|value: i64| total += value);
+            output.write(5).unwrap();
+            output.write(6).unwrap();
+        }
+
+        assert_eq!(total, 11);
+    }
+
+    #[test]
+    fn channels() {
+        let (sender, receiver) = mpsc::channel::<i64>();
+        let mut output = sender;
+        let mut input = receiver;
+
+        output.write(9).unwrap();
+
+        assert_eq!(input.read().unwrap(), 9);
+
+        drop(output);
+
+        assert!(matches!(input.read(), Err(ComputerError::InputEof)));
+    }
+
+    #[test]
+    fn text() {
+        let mut input = TextInput::new(BufReader::new("12\n-4\nx\n".as_bytes()));
+
+        assert_eq!(Input::<i64>::read(&mut input).unwrap(), 12);
+        assert_eq!(Input::<i64>::read(&mut input).unwrap(), -4);
+        assert!(matches!(Input::<i64>::read(&mut input), Err(ComputerError::UnparsableInput(_))));
+        assert!(matches!(Input::<i64>::read(&mut input), Err(ComputerError::InputEof)));
+
+        let mut writer: Vec<u8> = vec![];
+        {
+            let mut output = TextOutput::new(&mut writer);
+            output.write(7i64).unwrap();
+            output.write(-8i64).unwrap();
+        }
+
+        assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "7\n-8\n");
+    }
+
+    #[test]
+    fn ascii() {
+        let mut input: AsciiInput<i64, _> = AsciiInput::new(BufReader::new("go\r\nN\nend".as_bytes()));
+        let words: Vec<i64> = (0..7).map(|_| input.read().unwrap()).collect();
+
+        assert_eq!(words, vec![103, 111, 10, 78, 10, 101, 110]);
+        assert_eq!(input.read().unwrap(), 100);
+        assert_eq!(input.read().unwrap(), 10);
+        assert!(matches!(input.read(), Err(ComputerError::InputEof)));
+
+        let mut writer: Vec<u8> = vec![];
+        {
+            let mut output = AsciiOutput::new(&mut writer);
+            for value in &[1000i64, 79, 75, 10, 62, 128, -1] {
+                output.write(*value).unwrap();
+            }
+        }
+
+        assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "1000\nOK\n>\n128\n-1\n");
+    }
+
+    #[test]
+    fn ascii_machine() {
+        // Echoes two characters, then reports a large number.
+        let program: Program<i64> = "3,100,4,100,3,100,4,100,104,1000,99".parse().unwrap();
+        let mut writer: Vec<u8> = vec![];
+        let input = AsciiInput::new(BufReader::new("hi\n".as_bytes()));
+
+        Machine::from_program(&program, input, AsciiOutput::new(&mut writer)).compute().unwrap();
+
+        assert_eq!(std::str::from_utf8(writer.as_slice()).unwrap(), "hi\n1000\n");
+    }
+
+    #[test]
+    fn borrowed() {
+        fn write_one<O: Output<i64>>(mut output: O) {
+            output.write(1).unwrap();
+        }
+
+        let mut queue: VecDeque<i64> = VecDeque::new();
+
+        write_one(&mut queue);
+        write_one(&mut queue);
+
+        assert_eq!(queue.len(), 2);
+    }
+}
@@ -0,0 +1,323 @@
+//! Instruction level execution traces for postmortem comparison of runs.
+use crate::computer::{OpCode, Operation, Word};
+use crate::disassembler::mnemonic;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolvedOperand<W: Word> {
+    Value(W),
+    Address(usize)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IoEvent<W: Word> {
+    Input(W),
+    Output(W)
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceRecord<W: Word> {
+    pub step: u64,
+    pub address: usize,
+    pub instruction: W,
+    pub operation: Operation,
+    pub operands: Vec<ResolvedOperand<W>>,
+    pub writes: Vec<(usize, W)>,
+    pub io: Option<IoEvent<W>>,
+    /// The error the instruction failed with, which ends the trace.
+    pub fault: Option<String>
+}
+
+pub trait Tracer<W: Word> {
+    fn record(&mut self, record: &TraceRecord<W>) -> io::Result<()>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    addresses: Option<Range<usize>>,
+    opcodes: Option<Vec<i32>>
+}
+
+impl TraceFilter {
+
+    pub fn new() -> Self {
+        TraceFilter::default()
+    }
+
+    pub fn addresses(mut self, addresses: Range<usize>) -> Self {
+        self.addresses = Some(addresses);
+        self
+    }
+
+    pub fn opcodes(mut self, opcodes: &[i32]) -> Self {
+        self.opcodes = Some(opcodes.to_vec());
+        self
+    }
+
+    pub fn matches(&self, address: usize, opcode: &OpCode) -> bool {
+        let address_matches = match &self.addresses {
+            Some(addresses) => addresses.contains(&address),
+            None => true
+        };
+        let opcode_matches = match &self.opcodes {
+            Some(opcodes) => opcodes.contains(&opcode.code()),
+            None => true
+        };
+
+        address_matches && opcode_matches
+    }
+}
+
+fn json_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Quoted only when it has to be, so ordinary fields stay readable.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+fn modes(operation: &Operation) -> Vec<i32> {
+    (0..operation.parameter_count()).map(|index| operation.parameter_mode(index).code()).collect()
+}
+
+pub struct JsonLinesTracer<Wr: Write> {
+    writer: Wr
+}
+
+impl<Wr: Write> JsonLinesTracer<Wr> {
+    pub fn new(writer: Wr) -> Self {
+        JsonLinesTracer { writer }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_ref(&self) -> &Wr {
+        &self.writer
+    }
+}
+
+impl<W: Word, Wr: Write> Tracer<W> for JsonLinesTracer<Wr> {
+    fn record(&mut self, record: &TraceRecord<W>) -> io::Result<()> {
+        let modes: Vec<String> = modes(&record.operation).iter().map(i32::to_string).collect();
+        let operands: Vec<String> = record.operands.iter().map(|operand| match operand {
+            ResolvedOperand::Value(value) => format!("{{\"value\":{}}}", value),
+            ResolvedOperand::Address(address) => format!("{{\"address\":{}}}", address)
+        }).collect();
+        let writes: Vec<String> = record.writes.iter()
+            .map(|(address, value)| format!("{{\"address\":{},\"value\":{}}}", address, value))
+            .collect();
+        let io = match &record.io {
+            Some(IoEvent::Input(value)) => format!("{{\"input\":{}}}", value),
+            Some(IoEvent::Output(value)) => format!("{{\"output\":{}}}", value),
+            None => "null".to_owned()
+        };
+        let fault = record.fault.as_deref().map_or("null".to_owned(), json_string);
+
+        writeln!(
+            self.writer,
+            "{{\"step\":{},\"address\":{},\"instruction\":{},\"opcode\":\"{}\",\"modes\":[{}],\"operands\":[{}],\"writes\":[{}],\"io\":{},\"fault\":{}}}",
+            record.step,
+            record.address,
+            record.instruction,
+            mnemonic(&record.operation.opcode),
+            modes.join(","),
+            operands.join(","),
+            writes.join(","),
+            io,
+            fault
+        )
+    }
+}
+
+pub struct CsvTracer<Wr: Write> {
+    writer: Wr,
+    header_written: bool
+}
+
+impl<Wr: Write> CsvTracer<Wr> {
+    pub fn new(writer: Wr) -> Self {
+        CsvTracer { writer, header_written: false }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_ref(&self) -> &Wr {
+        &self.writer
+    }
+}
+
+// Lists inside a field are separated by semicolons, write targets are prefixed with @.
+impl<W: Word, Wr: Write> Tracer<W> for CsvTracer<Wr> {
+    fn record(&mut self, record: &TraceRecord<W>) -> io::Result<()> {
+        if !self.header_written {
+            writeln!(self.writer, "step,address,instruction,opcode,modes,operands,writes,io,fault")?;
+            self.header_written = true;
+        }
+
+        let modes: Vec<String> = modes(&record.operation).iter().map(i32::to_string).collect();
+        let operands: Vec<String> = record.operands.iter().map(|operand| match operand {
+            ResolvedOperand::Value(value) => value.to_string(),
+            ResolvedOperand::Address(address) => format!("@{}", address)
+        }).collect();
+        let writes: Vec<String> = record.writes.iter()
+            .map(|(address, value)| format!("{}={}", address, value))
+            .collect();
+        let io = match &record.io {
+            Some(IoEvent::Input(value)) => format!("in:{}", value),
+            Some(IoEvent::Output(value)) => format!("out:{}", value),
+            None => String::new()
+        };
+        let fault = record.fault.as_deref().map_or(String::new(), csv_field);
+
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{},{},{}",
+            record.step,
+            record.address,
+            record.instruction,
+            mnemonic(&record.operation.opcode),
+            modes.join(";"),
+            operands.join(";"),
+            writes.join(";"),
+            io,
+            fault
+        )
+    }
+}
+
+// Lets the caller keep a handle on a tracer owned by a running machine.
+impl<W: Word, T: Tracer<W>> Tracer<W> for Arc<Mutex<T>> {
+    fn record(&mut self, record: &TraceRecord<W>) -> io::Result<()> {
+        match self.lock() {
+            Ok(mut tracer) => tracer.record(record),
+            Err(_) => Err(io::Error::other("Tracer lock poisoned."))
+        }
+    }
+}
+
+impl<W: Word> Tracer<W> for Vec<TraceRecord<W>> {
+    fn record(&mut self, record: &TraceRecord<W>) -> io::Result<()> {
+        self.push(record.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CsvTracer, JsonLinesTracer, TraceFilter, TraceRecord, IoEvent, ResolvedOperand};
+    use crate::computer::{Computer, ComputerError};
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    fn run(program: &[i64], inputs: Vec<i64>, filter: TraceFilter) -> Vec<TraceRecord<i64>> {
+        let mut data = program.to_vec();
+        let records: Arc<Mutex<Vec<TraceRecord<i64>>>> = Arc::new(Mutex::new(vec![]));
+
+        let mut computer = Computer::new(&mut data, VecDeque::from(inputs), Vec::new());
+        computer.set_tracer(Box::new(records.clone()), filter);
+        computer.compute().unwrap();
+
+        let records = records.lock().unwrap().clone();
+        records
+    }
+
+    #[test]
+    fn records_every_instruction() {
+        let records = run(&[3,9,8,9,10,9,4,9,99,-1,8], vec![8], TraceFilter::new());
+
+        assert_eq!(records.len(), 4);
+        assert_eq!(records.iter().map(|record| record.address).collect::<Vec<_>>(), vec![0, 2, 6, 8]);
+
+        assert_eq!(records[0].operands, vec![ResolvedOperand::Address(9)]);
+        assert_eq!(records[0].writes, vec![(9, 8)]);
+        assert_eq!(records[0].io, Some(IoEvent::Input(8)));
+
+        assert_eq!(records[1].operands, vec![ResolvedOperand::Value(8), ResolvedOperand::Value(8), ResolvedOperand::Address(9)]);
+        assert_eq!(records[1].writes, vec![(9, 1)]);
+
+        assert_eq!(records[2].io, Some(IoEvent::Output(1)));
+        assert_eq!(records[3].step, 3);
+    }
+
+    #[test]
+    fn filters() {
+        let program = [3,9,8,9,10,9,4,9,99,-1,8];
+
+        let by_address = run(&program, vec![8], TraceFilter::new().addresses(2..7));
+        assert_eq!(by_address.iter().map(|record| record.address).collect::<Vec<_>>(), vec![2, 6]);
+
+        let by_opcode = run(&program, vec![8], TraceFilter::new().opcodes(&[4, 99]));
+        assert_eq!(by_opcode.iter().map(|record| record.step).collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut data = vec![3,9,8,9,10,9,4,9,99,-1,8];
+        let tracer = Arc::new(Mutex::new(JsonLinesTracer::new(vec![])));
+
+        let mut computer = Computer::new(&mut data, VecDeque::from(vec![7]), Vec::new());
+        computer.set_tracer(Box::new(tracer.clone()), TraceFilter::new());
+        computer.compute().unwrap();
+
+        let tracer = tracer.lock().unwrap();
+        assert_eq!(std::str::from_utf8(tracer.get_ref()).unwrap(), "\
+{\"step\":0,\"address\":0,\"instruction\":3,\"opcode\":\"IN\",\"modes\":[0],\"operands\":[{\"address\":9}],\"writes\":[{\"address\":9,\"value\":7}],\"io\":{\"input\":7},\"fault\":null}
+{\"step\":1,\"address\":2,\"instruction\":8,\"opcode\":\"EQ\",\"modes\":[0,0,0],\"operands\":[{\"value\":7},{\"value\":8},{\"address\":9}],\"writes\":[{\"address\":9,\"value\":0}],\"io\":null,\"fault\":null}
+{\"step\":2,\"address\":6,\"instruction\":4,\"opcode\":\"OUT\",\"modes\":[0],\"operands\":[{\"value\":0}],\"writes\":[],\"io\":{\"output\":0},\"fault\":null}
+{\"step\":3,\"address\":8,\"instruction\":99,\"opcode\":\"HLT\",\"modes\":[],\"operands\":[],\"writes\":[],\"io\":null,\"fault\":null}
+");
+    }
+
+    #[test]
+    fn csv() {
+        let mut data = vec![1101,2,3,5,104,0,99];
+        let tracer = Arc::new(Mutex::new(CsvTracer::new(vec![])));
+
+        let mut computer = Computer::new(&mut data, VecDeque::new(), Vec::new());
+        computer.set_tracer(Box::new(tracer.clone()), TraceFilter::new());
+        computer.compute().unwrap();
+
+        let tracer = tracer.lock().unwrap();
+        assert_eq!(std::str::from_utf8(tracer.get_ref()).unwrap(), "\
+step,address,instruction,opcode,modes,operands,writes,io,fault
+0,0,1101,ADD,1;1;0,2;3;@5,5=5,,
+1,4,104,OUT,1,5,,out:5,
+2,6,99,HLT,,,,,
+");
+    }
+
+    #[test]
+    fn records_the_faulting_instruction() {
+        let mut data = vec![1101,2,3,7,4,-7,99,0];
+        let records: Arc<Mutex<Vec<TraceRecord<i64>>>> = Arc::new(Mutex::new(vec![]));
+        let tracer = Arc::new(Mutex::new(CsvTracer::new(vec![])));
+
+        let mut computer = Computer::new(&mut data, VecDeque::new(), Vec::new());
+        computer.set_tracer(Box::new(records.clone()), TraceFilter::new());
+        assert!(matches!(computer.compute(), Err(ComputerError::OutOfBounds { address: -7, instruction: 4 })));
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].fault, None);
+        assert_eq!((records[1].step, records[1].address), (1, 4));
+        assert!(records[1].operands.is_empty());
+        assert_eq!(records[1].fault.as_deref(), Some("Invalid memory address: [-7] accessed by instruction at address 4"));
+
+        let mut data = vec![1101,2,3,7,4,-7,99,0];
+        let mut computer = Computer::new(&mut data, VecDeque::new(), Vec::new());
+        computer.set_tracer(Box::new(tracer.clone()), TraceFilter::new());
+        assert!(computer.compute().is_err());
+
+        let tracer = tracer.lock().unwrap();
+        assert_eq!(std::str::from_utf8(tracer.get_ref()).unwrap(), "\
+step,address,instruction,opcode,modes,operands,writes,io,fault
+0,0,1101,ADD,1;1;0,2;3;@7,7=5,,
+1,4,4,OUT,0,,,,Invalid memory address: [-7] accessed by instruction at address 4
+");
+    }
+}
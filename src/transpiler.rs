@@ -0,0 +1,494 @@
+//! Ahead-of-time compiler from Intcode images to Rust modules, and the runtime those modules call.
+use crate::computer::{Computer, ComputerError, OpCode, Operation, ParameterMode, Termination};
+use crate::control_flow::ControlFlowGraph;
+use crate::disassembler::Line;
+use crate::memory::Memory;
+use crate::streams::{Input, Output};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+/// State for transpiled code. Stores into compiled words are remembered so that a block whose words
+/// no longer match the image it was compiled from can be handed to the interpreter instead.
+pub struct Native<'a, In: Input<i64>, Out: Output<i64>> {
+    memory: Memory<'a, i64>,
+    relative_base: i64,
+    image: &'static [i64],
+    code: Vec<bool>,
+    // One bit per image word, set while a compiled word differs from the image, and how many are set.
+    mismatched: Vec<u64>,
+    mismatches: usize,
+    source: In,
+    sink: Out
+}
+
+// Generated modules only call what their own programs need.
+#[allow(dead_code)]
+impl<'a, In: Input<i64>, Out: Output<i64>> Native<'a, In, Out> {
+
+    pub fn new(
+        memory: &'a mut [i64],
+        image: &'static [i64],
+        code: &[(usize, usize)],
+        dynamic: &[usize],
+        source: In,
+        sink: Out
+    ) -> Self {
+        let mut compiled = vec![false; image.len()];
+        for (start, end) in code {
+            compiled[*start..*end].iter_mut().for_each(|word| *word = true);
+        }
+        for address in dynamic {
+            if let Some(word) = compiled.get_mut(*address) {
+                *word = false;
+            }
+        }
+
+        let mut native = Native {
+            memory: Memory::new(memory),
+            relative_base: 0,
+            image,
+            code: compiled,
+            mismatched: vec![0; image.len().div_ceil(64)],
+            mismatches: 0,
+            source,
+            sink
+        };
+
+        for address in 0..image.len().min(native.memory.image_len()) {
+            native.track(address);
+        }
+
+        native
+    }
+
+    fn is_mismatched(&self, address: usize) -> bool {
+        self.mismatched[address / 64] & (1 << (address % 64)) != 0
+    }
+
+    // Brings the mismatch bit for `address` up to date with memory, if the word is compiled.
+    fn track(&mut self, address: usize) {
+        if self.code.get(address) != Some(&true) {
+            return;
+        }
+
+        let mismatched = self.memory.read(address) != self.image[address];
+        if mismatched != self.is_mismatched(address) {
+            self.mismatched[address / 64] ^= 1 << (address % 64);
+            if mismatched {
+                self.mismatches += 1;
+            } else {
+                self.mismatches -= 1;
+            }
+        }
+    }
+
+    /// Whether the memory is as long as the image, so compiled addresses and the end of the program line up.
+    pub fn compatible(&self) -> bool {
+        self.memory.image_len() == self.image.len()
+    }
+
+    fn to_address(instruction: usize, address: i64) -> Result<usize, ComputerError> {
+        usize::try_from(address).map_err(|_| ComputerError::OutOfBounds { address: address.into(), instruction })
+    }
+
+    /// The word at `address`, for operands that are read from memory rather than compiled in.
+    pub fn word(&self, address: usize) -> i64 {
+        self.memory.read(address)
+    }
+
+    pub fn load(&self, instruction: usize, address: i64) -> Result<i64, ComputerError> {
+        Ok(self.memory.read(Self::to_address(instruction, address)?))
+    }
+
+    pub fn relative(&self, instruction: usize, offset: i64) -> Result<i64, ComputerError> {
+        self.relative_base.checked_add(offset).ok_or(ComputerError::Overflow { instruction })
+    }
+
+    pub fn store(&mut self, address: usize, value: i64) {
+        self.memory.write(address, value);
+        self.track(address);
+    }
+
+    pub fn target(&self, instruction: usize, address: i64) -> Result<usize, ComputerError> {
+        Self::to_address(instruction, address)
+    }
+
+    /// Whether any compiled word in `start..end` differs from the image.
+    pub fn changed(&self, start: usize, end: usize) -> bool {
+        self.mismatches > 0 && (start..end.min(self.image.len())).any(|address| self.is_mismatched(address))
+    }
+
+    pub fn input(&mut self) -> Result<i64, ComputerError> {
+        self.source.read()
+    }
+
+    pub fn output(&mut self, value: i64) -> Result<(), ComputerError> {
+        self.sink.write(value)
+    }
+
+    pub fn adjust(&mut self, instruction: usize, offset: i64) -> Result<(), ComputerError> {
+        self.relative_base = self.relative(instruction, offset)?;
+        Ok(())
+    }
+
+    /// Hands the rest of the run to the interpreter, starting at `address`.
+    pub fn interpret(self, address: usize) -> Result<Termination, ComputerError> {
+        Computer::resume(self.memory, address, self.relative_base, self.source, self.sink).compute()
+    }
+}
+
+struct Generator<'a> {
+    image_len: usize,
+    dynamic: &'a [usize],
+    source: String
+}
+
+impl<'a> Generator<'a> {
+
+    fn word(&self, address: usize, value: i64) -> String {
+        if self.dynamic.contains(&address) {
+            format!("native.word({})", address)
+        } else {
+            value.to_string()
+        }
+    }
+
+    fn parameter(&self, address: usize, operation: &Operation, operands: &[i64], index: usize) -> String {
+        let word = self.word(address + index + 1, operands[index]);
+
+        match operation.parameter_mode(index) {
+            ParameterMode::Position => format!("native.load({}, {})?", address, word),
+            ParameterMode::Immediate => word,
+            ParameterMode::Relative => format!("native.load({}, native.relative({}, {})?)?", address, address, word)
+        }
+    }
+
+    // Modes follow `Computer::get_storage_address`; negative or patched words are checked at run time.
+    fn destination(&self, address: usize, operation: &Operation, operands: &[i64], index: usize) -> String {
+        let word = self.word(address + index + 1, operands[index]);
+
+        match operation.parameter_mode(index) {
+            ParameterMode::Relative => format!("native.target({}, native.relative({}, {})?)?", address, address, word),
+            _ if !self.dynamic.contains(&(address + index + 1)) && operands[index] >= 0 => word,
+            _ => format!("native.target({}, {})?", address, word)
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        writeln!(self.source, "                {}", text).unwrap();
+    }
+
+    // Emits one block as a match arm evaluating to the next address, unless it returns.
+    fn block(&mut self, start: usize, lines: &[Line<i64>]) {
+        let end = lines.last().map_or(start, |line| match line {
+            Line::Instruction { address, .. } | Line::Data { address, .. } => address + line.size()
+        });
+
+        writeln!(self.source, "            {} => {{", start).unwrap();
+        self.line(&format!("if native.changed({}, {}) {{", start, end));
+        self.line(&format!("    return native.interpret({});", start));
+        self.line("}");
+
+        for (index, line) in lines.iter().enumerate() {
+            self.line(&format!("// {}", line));
+
+            let (address, operation, operands) = match line {
+                Line::Instruction { address, operation, operands } if !self.dynamic.contains(address) =>
+                    (*address, operation, operands),
+                Line::Instruction { address, .. } | Line::Data { address, .. } => {
+                    self.line(&format!("return native.interpret({});", address));
+                    break;
+                }
+            };
+            let next = address + line.size();
+            // Falling through past the image ends the run, while jumps past it go to the interpreter.
+            let fall_through = if next < self.image_len { next.to_string() } else { "return Ok(Termination::RanOffEnd)".to_owned() };
+            let parameter = |index: usize| self.parameter(address, operation, operands, index);
+
+            let loads = match operation.opcode.storage_parameter() {
+                Some(2) => Some(format!("let (left, right) = ({}, {});", parameter(0), parameter(1))),
+                _ => None
+            };
+            let overflow = format!("ok_or(ComputerError::Overflow {{ instruction: {} }})?", address);
+            let value = match operation.opcode {
+                OpCode::Addition => Some(format!("i64::checked_add(left, right).{}", overflow)),
+                OpCode::Multiplication => Some(format!("i64::checked_mul(left, right).{}", overflow)),
+                OpCode::LessThan => Some("(left < right) as i64".to_owned()),
+                OpCode::Equals => Some("(left == right) as i64".to_owned()),
+                OpCode::Write => Some("native.input()?".to_owned()),
+                OpCode::Output => {
+                    let text = format!("native.output({})?;", parameter(0));
+                    self.line(&text);
+                    None
+                },
+                OpCode::AdjustRelativeBase => {
+                    let text = format!("native.adjust({}, {})?;", address, parameter(0));
+                    self.line(&text);
+                    None
+                },
+                OpCode::Halt => {
+                    self.line("return Ok(Termination::Halted);");
+                    None
+                },
+                OpCode::JumpIfTrue | OpCode::JumpIfFalse => {
+                    let comparison = if let OpCode::JumpIfTrue = operation.opcode { "!=" } else { "==" };
+                    let target = match (operation.parameter_mode(1), self.dynamic.contains(&(address + 2))) {
+                        (ParameterMode::Immediate, false) if operands[1] >= 0 => operands[1].to_string(),
+                        _ => format!("native.target({}, {})?", address, parameter(1))
+                    };
+                    let text = format!("if {} {} 0 {{ {} }} else {{ {} }}", parameter(0), comparison, target, fall_through);
+                    self.line(&text);
+                    None
+                },
+                #[cfg(test)]
+                OpCode::Extension { .. } => unreachable!("Images are decoded without extensions.")
+            };
+
+            // Like the interpreter, operands are loaded before the target is resolved, and both before
+            // the result can overflow or input is read.
+            if let (Some(value), Some(index)) = (value, operation.opcode.storage_parameter()) {
+                if let Some(loads) = loads {
+                    self.line(&loads);
+                }
+                let destination = self.destination(address, operation, operands, index);
+                self.line(&format!("let target = {};", destination));
+                self.line(&format!("let value = {};", value));
+                self.line("native.store(target, value);");
+
+                if next < end {
+                    self.line(&format!("if native.changed({}, {}) {{", next, end));
+                    self.line(&format!("    return native.interpret({});", next));
+                    self.line("}");
+                }
+            }
+
+            let ends_block = matches!(operation.opcode, OpCode::Halt | OpCode::JumpIfTrue | OpCode::JumpIfFalse);
+            if index + 1 == lines.len() && !ends_block {
+                self.line(&fall_through);
+            }
+        }
+
+        writeln!(self.source, "            }},").unwrap();
+    }
+}
+
+// Merges the words of every decoded instruction into sorted, disjoint ranges.
+fn code_ranges(graph: &ControlFlowGraph<i64>) -> Vec<(usize, usize)> {
+    let mut words: Vec<(usize, usize)> = graph.blocks.values()
+        .flat_map(|block| block.lines.iter())
+        .filter_map(|line| match line {
+            Line::Instruction { address, .. } => Some((*address, address + line.size())),
+            Line::Data { .. } => None
+        })
+        .collect();
+    words.sort_unstable();
+
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for (start, end) in words {
+        match ranges.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end.max(*last_end),
+            _ => ranges.push((start, end))
+        }
+    }
+
+    ranges
+}
+
+/// Compiles every block reachable from address 0 into a Rust module with a `run` function. Words
+/// at `dynamic` addresses, which must be operands, are read from memory on each use so callers can
+/// patch them, as day 2 does with its noun and verb.
+pub fn transpile(image: &[i64], dynamic: &[usize]) -> String {
+    let graph = ControlFlowGraph::build(image);
+    let mut generator = Generator { image_len: image.len(), dynamic, source: String::new() };
+
+    let words: Vec<String> = image.chunks(16)
+        .map(|chunk| format!("    {},", chunk.iter().map(i64::to_string).collect::<Vec<String>>().join(", ")))
+        .collect();
+    let ranges: Vec<String> = code_ranges(&graph).iter().map(|(start, end)| format!("({}, {})", start, end)).collect();
+    let dynamic: Vec<String> = dynamic.iter().map(usize::to_string).collect();
+
+    let source = &mut generator.source;
+    writeln!(source, "//! Generated by `transpile` from a {} word Intcode image. Regenerate rather than edit.", image.len()).unwrap();
+    writeln!(source, "#![allow(clippy::all, unreachable_code, unused_assignments, unused_mut)]").unwrap();
+    writeln!(source, "use crate::computer::{{ComputerError, Termination}};").unwrap();
+    writeln!(source, "use crate::streams::{{Input, Output}};").unwrap();
+    writeln!(source, "use crate::transpiler::Native;").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "pub static IMAGE: [i64; {}] = [", image.len()).unwrap();
+    for words in words {
+        writeln!(source, "{}", words).unwrap();
+    }
+    writeln!(source, "];").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "// Word ranges compiled below, and words read from memory on each use instead.").unwrap();
+    writeln!(source, "const CODE: &[(usize, usize)] = &[{}];", ranges.join(", ")).unwrap();
+    writeln!(source, "const DYNAMIC: &[usize] = &[{}];", dynamic.join(", ")).unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "/// Behaves like `Computer::new(memory, source, sink).compute()`, without step limits or tracing.").unwrap();
+    writeln!(source, "pub fn run<In: Input<i64>, Out: Output<i64>>(memory: &mut [i64], source: In, sink: Out) -> Result<Termination, ComputerError> {{").unwrap();
+    writeln!(source, "    let mut native = Native::new(memory, &IMAGE, CODE, DYNAMIC, source, sink);").unwrap();
+    writeln!(source, "    if !native.compatible() {{").unwrap();
+    writeln!(source, "        return native.interpret(0);").unwrap();
+    writeln!(source, "    }}").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "    let mut address = 0;").unwrap();
+    writeln!(source, "    loop {{").unwrap();
+    writeln!(source, "        address = match address {{").unwrap();
+
+    for block in graph.blocks.values() {
+        generator.block(block.start, &block.lines);
+    }
+
+    let source = &mut generator.source;
+    writeln!(source, "            address => return native.interpret(address)").unwrap();
+    writeln!(source, "        }};").unwrap();
+    writeln!(source, "    }}").unwrap();
+    writeln!(source, "}}").unwrap();
+
+    generator.source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{transpile, Native};
+    use crate::assembler::assemble;
+    use crate::computer::{Computer, ComputerError, Termination};
+    use crate::{sample_native, two_native};
+    use std::collections::VecDeque;
+
+    // Regenerate sample_native.rs from this with the assemble and transpile subcommands.
+    const SAMPLE: &str = "
+    in   [n]
+    arb  #100
+loop:
+    mul  [n], #2, [rb+0]
+    out  [rb+0]
+    add  [n], #1, [n]
+    lt   [n], #5, [t]
+    jnz  [t], #loop
+    jnz  [n], [target]
+rewrite:
+    lt   [n], #7, [t]
+    mul  [t], #4, [patch]
+patch:
+    out  [n]
+    hlt
+n:      data 0
+t:      data 0
+target: data rewrite
+";
+
+    type Run = fn(&mut [i64], VecDeque<i64>, &mut Vec<i64>) -> Result<Termination, ComputerError>;
+
+    // Runs the interpreter and the compiled code on their own copies of `memory`, and checks they
+    // end the same way with the same outputs and memory.
+    fn differential(run: Run, memory: &[i64], input: &[i64]) -> Result<Termination, String> {
+        let mut interpreted_memory = memory.to_vec();
+        let mut interpreted_outputs = vec![];
+        let interpreted = Computer::new(&mut interpreted_memory, input.iter().copied().collect::<VecDeque<i64>>(), &mut interpreted_outputs)
+            .compute()
+            .map_err(|error| error.to_string());
+
+        let mut native_memory = memory.to_vec();
+        let mut native_outputs = vec![];
+        let native = run(&mut native_memory, input.iter().copied().collect(), &mut native_outputs)
+            .map_err(|error| error.to_string());
+
+        assert_eq!(native, interpreted, "for memory {:?} and input {:?}", memory, input);
+        assert_eq!(native_outputs, interpreted_outputs, "for memory {:?} and input {:?}", memory, input);
+        assert_eq!(native_memory, interpreted_memory, "for memory {:?} and input {:?}", memory, input);
+
+        native
+    }
+
+    #[test]
+    fn golden() {
+        assert_eq!(transpile(&two_native::IMAGE, &[1, 2]), include_str!("two_native.rs"));
+        assert_eq!(transpile(&assemble::<i64>(SAMPLE).unwrap(), &[]), include_str!("sample_native.rs"));
+    }
+
+    #[test]
+    fn day_two_sweep() {
+        for noun in (-1..100).step_by(3) {
+            for verb in (-1..100).step_by(7) {
+                let mut memory = two_native::IMAGE.to_vec();
+                memory[1] = noun;
+                memory[2] = verb;
+
+                let result = differential(|memory, source, sink| two_native::run(memory, source, sink), &memory, &[]);
+                assert_eq!(result.is_ok(), noun >= 0 && verb >= 0);
+            }
+        }
+    }
+
+    #[test]
+    fn falls_back_when_code_differs() {
+        let run: Run = |memory, source, sink| two_native::run(memory, source, sink);
+
+        // A multiplication patched over the first addition, then a different program entirely.
+        let mut memory = two_native::IMAGE.to_vec();
+        memory[0] = 2;
+        differential(run, &memory, &[]).unwrap();
+        differential(run, &[1, 0, 0, 0, 99], &[]).unwrap();
+    }
+
+    #[test]
+    fn overflow() {
+        // Day 2 adding the largest word to itself, and the sample doubling it.
+        let mut memory = two_native::IMAGE.to_vec();
+        memory[1] = 144;
+        memory[2] = 144;
+        memory[144] = i64::MAX;
+
+        let overflow = Err("Arithmetic overflow in instruction at address 0".to_owned());
+        assert_eq!(differential(|memory, source, sink| two_native::run(memory, source, sink), &memory, &[]), overflow);
+        assert!(differential(|memory, source, sink| sample_native::run(memory, source, sink), &sample_native::IMAGE, &[i64::MAX]).is_err());
+    }
+
+    #[test]
+    fn runs_code_written_past_image() {
+        let run: Run = |memory, source, sink| sample_native::run(memory, source, sink);
+
+        // Jumps to the doubled counter the loop leaves at the relative base, past the end of the
+        // image, which runs as an EQ storing to address 0 and then reaches a word never written.
+        let mut memory = sample_native::IMAGE.to_vec();
+        memory[37] = 100;
+        assert_eq!(differential(run, &memory, &[0]), Ok(Termination::RanOffEnd));
+
+        // Falling through the last word ends the run without the interpreter.
+        assert!(transpile(&[1101, 2, 3, 0], &[]).contains("                return Ok(Termination::RanOffEnd)\n"));
+    }
+
+    #[test]
+    fn tracks_mismatched_words() {
+        static IMAGE: [i64; 5] = [1101, 1, 2, 3, 99];
+        let mut memory = IMAGE.to_vec();
+        memory[1] = 7;
+        let mut native = Native::new(&mut memory, &IMAGE, &[(0, 4)], &[2], VecDeque::new(), vec![]);
+
+        assert!(native.changed(0, 2));
+        native.store(1, 1);
+        assert!(!native.changed(0, 5));
+
+        // Dynamic and uncompiled words are read on each use, so writing them changes nothing.
+        native.store(2, 5);
+        native.store(4, 5);
+        assert!(!native.changed(0, 5));
+
+        native.store(3, 4);
+        assert!(native.changed(3, 4));
+        assert!(!native.changed(0, 3));
+    }
+
+    #[test]
+    fn self_modifying_sample() {
+        let run: Run = |memory, source, sink| sample_native::run(memory, source, sink);
+
+        // The rewrite stores the opcode already there until the counter ends at 7, then an invalid one.
+        for input in &[-3, 0, 4, 5, 6, 7, 9] {
+            let result = differential(run, &sample_native::IMAGE, &[*input]);
+            assert_eq!(result.is_ok(), *input < 6, "for input {}", input);
+        }
+        assert!(differential(run, &sample_native::IMAGE, &[]).is_err());
+    }
+}
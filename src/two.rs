@@ -1,32 +1,67 @@
 //! Day 2
 use crate::computer;
+use crate::streams::{TextInput, TextOutput};
+use crate::program::Program;
+use crate::two_native;
+use std::collections::VecDeque;
 use std::io::{self};
 
-pub fn solve_part_one(input: &mut [i32]) -> i32 {
+// Far more than any valid noun and verb need; candidates running longer are abandoned.
+const STEP_LIMIT: u64 = 100_000;
+
+pub fn solve_part_one<W: computer::Word>(input: &mut [W]) -> W {
     let stdin = io::stdin();
-    let mut computer = computer::Computer::new(input, stdin.lock(), io::stdout());
+    let mut computer = computer::Computer::new(input, TextInput::new(stdin.lock()), TextOutput::new(io::stdout()));
 
-    computer.compute();
+    computer.compute().expect("Day 2 program failed.");
 
     input[0]
 }
 
-pub fn solve_part_two(input: Vec<i32>, target: i32) -> i32 {
+pub fn solve_part_two<W: computer::Word>(input: Vec<W>, target: W) -> W {
+    let program = Program::new(input);
+
     for noun in 0..99 {
         for verb in 0..99 {
-            let mut copied_input = input.clone();
-
-            copied_input[1] = noun;
-            copied_input[2] = verb;
-
             let stdin = io::stdin();
-            let mut computer = computer::Computer::new(&mut copied_input, stdin.lock(), io::stdout());
+            let mut computer = computer::Machine::from_program(&program, TextInput::new(stdin.lock()), TextOutput::new(io::stdout()));
 
-            computer.compute();
+            computer.write_memory(1, W::from(noun)).expect("No devices are attached.");
+            computer.write_memory(2, W::from(verb)).expect("No devices are attached.");
+            computer.set_step_limit(Some(STEP_LIMIT));
 
-            let result = copied_input[0];
+            if computer.compute().is_err() {
+                continue;
+            }
+
+            let result = computer.read_memory(0);
 
             if result == target {
+                return W::from(100 * noun + verb);
+            }
+        }
+    }
+
+    panic!();
+}
+
+/// As `solve_part_two`, but sweeps with the puzzle input compiled ahead of time. Compiled code has
+/// no step limit, so any other program goes through the interpreter.
+pub fn solve_part_two_native(input: Vec<i64>, target: i64) -> i64 {
+    let compiled = input.len() == two_native::IMAGE.len() && input.iter().zip(two_native::IMAGE.iter())
+        .enumerate()
+        .all(|(address, (word, compiled))| address == 1 || address == 2 || word == compiled);
+    if !compiled {
+        return solve_part_two(input, target);
+    }
+
+    for noun in 0..99 {
+        for verb in 0..99 {
+            let mut memory = input.clone();
+            memory[1] = noun;
+            memory[2] = verb;
+
+            if two_native::run(&mut memory, VecDeque::new(), Vec::new()).is_ok() && memory[0] == target {
                 return 100 * noun + verb;
             }
         }
@@ -56,4 +91,19 @@ mod tests {
     fn given_four() {
         assert_eq!(30, super::solve_part_one(&mut[1, 1, 1, 4, 99, 5, 6, 0, 99]))
     }
+
+    #[test]
+    fn skips_looping_candidates() {
+        // Every noun of 0 ends in a jump to itself.
+        assert_eq!(104, super::solve_part_two(vec![1, 0, 0, 0, 1005, 1, 10, 1105, 1, 7, 99], 1006))
+    }
+
+    #[test]
+    fn native_sweep_agrees() {
+        let input = crate::two_native::IMAGE.to_vec();
+        let target = 19_690_720;
+
+        assert_eq!(super::solve_part_two_native(input.clone(), target), super::solve_part_two(input, target));
+        assert_eq!(104, super::solve_part_two_native(vec![1, 0, 0, 0, 1005, 1, 10, 1105, 1, 7, 99], 1006));
+    }
 }
@@ -0,0 +1,324 @@
+//! Generated by `transpile` from a 145 word Intcode image. Regenerate rather than edit.
+#![allow(clippy::all, unreachable_code, unused_assignments, unused_mut)]
+use crate::computer::{ComputerError, Termination};
+use crate::streams::{Input, Output};
+use crate::transpiler::Native;
+
+pub static IMAGE: [i64; 145] = [
+    1, 0, 0, 3, 1, 1, 2, 3, 1, 3, 4, 3, 1, 5, 0, 3,
+    2, 10, 1, 19, 1, 19, 5, 23, 1, 23, 9, 27, 2, 27, 6, 31,
+    1, 31, 6, 35, 2, 35, 9, 39, 1, 6, 39, 43, 2, 10, 43, 47,
+    1, 47, 9, 51, 1, 51, 6, 55, 1, 55, 6, 59, 2, 59, 10, 63,
+    1, 6, 63, 67, 2, 6, 67, 71, 1, 71, 5, 75, 2, 13, 75, 79,
+    1, 10, 79, 83, 1, 5, 83, 87, 2, 87, 10, 91, 1, 5, 91, 95,
+    2, 95, 6, 99, 1, 99, 6, 103, 2, 103, 6, 107, 2, 107, 9, 111,
+    1, 111, 5, 115, 1, 115, 6, 119, 2, 6, 119, 123, 1, 5, 123, 127,
+    1, 127, 13, 131, 1, 2, 131, 135, 1, 135, 10, 0, 99, 2, 14, 0,
+    0,
+];
+
+// Word ranges compiled below, and words read from memory on each use instead.
+const CODE: &[(usize, usize)] = &[(0, 141)];
+const DYNAMIC: &[usize] = &[1, 2];
+
+/// Behaves like `Computer::new(memory, source, sink).compute()`, without step limits or tracing.
+pub fn run<In: Input<i64>, Out: Output<i64>>(memory: &mut [i64], source: In, sink: Out) -> Result<Termination, ComputerError> {
+    let mut native = Native::new(memory, &IMAGE, CODE, DYNAMIC, source, sink);
+    if !native.compatible() {
+        return native.interpret(0);
+    }
+
+    let mut address = 0;
+    loop {
+        address = match address {
+            0 => {
+                if native.changed(0, 141) {
+                    return native.interpret(0);
+                }
+                // 0000: ADD  [0], [0], [3]
+                let (left, right) = (native.load(0, native.word(1))?, native.load(0, native.word(2))?);
+                let target = 3;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 0 })?;
+                native.store(target, value);
+                if native.changed(4, 141) {
+                    return native.interpret(4);
+                }
+                // 0004: ADD  [1], [2], [3]
+                let (left, right) = (native.load(4, 1)?, native.load(4, 2)?);
+                let target = 3;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 4 })?;
+                native.store(target, value);
+                if native.changed(8, 141) {
+                    return native.interpret(8);
+                }
+                // 0008: ADD  [3], [4], [3]
+                let (left, right) = (native.load(8, 3)?, native.load(8, 4)?);
+                let target = 3;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 8 })?;
+                native.store(target, value);
+                if native.changed(12, 141) {
+                    return native.interpret(12);
+                }
+                // 0012: ADD  [5], [0], [3]
+                let (left, right) = (native.load(12, 5)?, native.load(12, 0)?);
+                let target = 3;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 12 })?;
+                native.store(target, value);
+                if native.changed(16, 141) {
+                    return native.interpret(16);
+                }
+                // 0016: MUL  [10], [1], [19]
+                let (left, right) = (native.load(16, 10)?, native.load(16, 1)?);
+                let target = 19;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 16 })?;
+                native.store(target, value);
+                if native.changed(20, 141) {
+                    return native.interpret(20);
+                }
+                // 0020: ADD  [19], [5], [23]
+                let (left, right) = (native.load(20, 19)?, native.load(20, 5)?);
+                let target = 23;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 20 })?;
+                native.store(target, value);
+                if native.changed(24, 141) {
+                    return native.interpret(24);
+                }
+                // 0024: ADD  [23], [9], [27]
+                let (left, right) = (native.load(24, 23)?, native.load(24, 9)?);
+                let target = 27;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 24 })?;
+                native.store(target, value);
+                if native.changed(28, 141) {
+                    return native.interpret(28);
+                }
+                // 0028: MUL  [27], [6], [31]
+                let (left, right) = (native.load(28, 27)?, native.load(28, 6)?);
+                let target = 31;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 28 })?;
+                native.store(target, value);
+                if native.changed(32, 141) {
+                    return native.interpret(32);
+                }
+                // 0032: ADD  [31], [6], [35]
+                let (left, right) = (native.load(32, 31)?, native.load(32, 6)?);
+                let target = 35;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 32 })?;
+                native.store(target, value);
+                if native.changed(36, 141) {
+                    return native.interpret(36);
+                }
+                // 0036: MUL  [35], [9], [39]
+                let (left, right) = (native.load(36, 35)?, native.load(36, 9)?);
+                let target = 39;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 36 })?;
+                native.store(target, value);
+                if native.changed(40, 141) {
+                    return native.interpret(40);
+                }
+                // 0040: ADD  [6], [39], [43]
+                let (left, right) = (native.load(40, 6)?, native.load(40, 39)?);
+                let target = 43;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 40 })?;
+                native.store(target, value);
+                if native.changed(44, 141) {
+                    return native.interpret(44);
+                }
+                // 0044: MUL  [10], [43], [47]
+                let (left, right) = (native.load(44, 10)?, native.load(44, 43)?);
+                let target = 47;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 44 })?;
+                native.store(target, value);
+                if native.changed(48, 141) {
+                    return native.interpret(48);
+                }
+                // 0048: ADD  [47], [9], [51]
+                let (left, right) = (native.load(48, 47)?, native.load(48, 9)?);
+                let target = 51;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 48 })?;
+                native.store(target, value);
+                if native.changed(52, 141) {
+                    return native.interpret(52);
+                }
+                // 0052: ADD  [51], [6], [55]
+                let (left, right) = (native.load(52, 51)?, native.load(52, 6)?);
+                let target = 55;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 52 })?;
+                native.store(target, value);
+                if native.changed(56, 141) {
+                    return native.interpret(56);
+                }
+                // 0056: ADD  [55], [6], [59]
+                let (left, right) = (native.load(56, 55)?, native.load(56, 6)?);
+                let target = 59;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 56 })?;
+                native.store(target, value);
+                if native.changed(60, 141) {
+                    return native.interpret(60);
+                }
+                // 0060: MUL  [59], [10], [63]
+                let (left, right) = (native.load(60, 59)?, native.load(60, 10)?);
+                let target = 63;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 60 })?;
+                native.store(target, value);
+                if native.changed(64, 141) {
+                    return native.interpret(64);
+                }
+                // 0064: ADD  [6], [63], [67]
+                let (left, right) = (native.load(64, 6)?, native.load(64, 63)?);
+                let target = 67;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 64 })?;
+                native.store(target, value);
+                if native.changed(68, 141) {
+                    return native.interpret(68);
+                }
+                // 0068: MUL  [6], [67], [71]
+                let (left, right) = (native.load(68, 6)?, native.load(68, 67)?);
+                let target = 71;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 68 })?;
+                native.store(target, value);
+                if native.changed(72, 141) {
+                    return native.interpret(72);
+                }
+                // 0072: ADD  [71], [5], [75]
+                let (left, right) = (native.load(72, 71)?, native.load(72, 5)?);
+                let target = 75;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 72 })?;
+                native.store(target, value);
+                if native.changed(76, 141) {
+                    return native.interpret(76);
+                }
+                // 0076: MUL  [13], [75], [79]
+                let (left, right) = (native.load(76, 13)?, native.load(76, 75)?);
+                let target = 79;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 76 })?;
+                native.store(target, value);
+                if native.changed(80, 141) {
+                    return native.interpret(80);
+                }
+                // 0080: ADD  [10], [79], [83]
+                let (left, right) = (native.load(80, 10)?, native.load(80, 79)?);
+                let target = 83;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 80 })?;
+                native.store(target, value);
+                if native.changed(84, 141) {
+                    return native.interpret(84);
+                }
+                // 0084: ADD  [5], [83], [87]
+                let (left, right) = (native.load(84, 5)?, native.load(84, 83)?);
+                let target = 87;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 84 })?;
+                native.store(target, value);
+                if native.changed(88, 141) {
+                    return native.interpret(88);
+                }
+                // 0088: MUL  [87], [10], [91]
+                let (left, right) = (native.load(88, 87)?, native.load(88, 10)?);
+                let target = 91;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 88 })?;
+                native.store(target, value);
+                if native.changed(92, 141) {
+                    return native.interpret(92);
+                }
+                // 0092: ADD  [5], [91], [95]
+                let (left, right) = (native.load(92, 5)?, native.load(92, 91)?);
+                let target = 95;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 92 })?;
+                native.store(target, value);
+                if native.changed(96, 141) {
+                    return native.interpret(96);
+                }
+                // 0096: MUL  [95], [6], [99]
+                let (left, right) = (native.load(96, 95)?, native.load(96, 6)?);
+                let target = 99;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 96 })?;
+                native.store(target, value);
+                if native.changed(100, 141) {
+                    return native.interpret(100);
+                }
+                // 0100: ADD  [99], [6], [103]
+                let (left, right) = (native.load(100, 99)?, native.load(100, 6)?);
+                let target = 103;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 100 })?;
+                native.store(target, value);
+                if native.changed(104, 141) {
+                    return native.interpret(104);
+                }
+                // 0104: MUL  [103], [6], [107]
+                let (left, right) = (native.load(104, 103)?, native.load(104, 6)?);
+                let target = 107;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 104 })?;
+                native.store(target, value);
+                if native.changed(108, 141) {
+                    return native.interpret(108);
+                }
+                // 0108: MUL  [107], [9], [111]
+                let (left, right) = (native.load(108, 107)?, native.load(108, 9)?);
+                let target = 111;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 108 })?;
+                native.store(target, value);
+                if native.changed(112, 141) {
+                    return native.interpret(112);
+                }
+                // 0112: ADD  [111], [5], [115]
+                let (left, right) = (native.load(112, 111)?, native.load(112, 5)?);
+                let target = 115;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 112 })?;
+                native.store(target, value);
+                if native.changed(116, 141) {
+                    return native.interpret(116);
+                }
+                // 0116: ADD  [115], [6], [119]
+                let (left, right) = (native.load(116, 115)?, native.load(116, 6)?);
+                let target = 119;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 116 })?;
+                native.store(target, value);
+                if native.changed(120, 141) {
+                    return native.interpret(120);
+                }
+                // 0120: MUL  [6], [119], [123]
+                let (left, right) = (native.load(120, 6)?, native.load(120, 119)?);
+                let target = 123;
+                let value = i64::checked_mul(left, right).ok_or(ComputerError::Overflow { instruction: 120 })?;
+                native.store(target, value);
+                if native.changed(124, 141) {
+                    return native.interpret(124);
+                }
+                // 0124: ADD  [5], [123], [127]
+                let (left, right) = (native.load(124, 5)?, native.load(124, 123)?);
+                let target = 127;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 124 })?;
+                native.store(target, value);
+                if native.changed(128, 141) {
+                    return native.interpret(128);
+                }
+                // 0128: ADD  [127], [13], [131]
+                let (left, right) = (native.load(128, 127)?, native.load(128, 13)?);
+                let target = 131;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 128 })?;
+                native.store(target, value);
+                if native.changed(132, 141) {
+                    return native.interpret(132);
+                }
+                // 0132: ADD  [2], [131], [135]
+                let (left, right) = (native.load(132, 2)?, native.load(132, 131)?);
+                let target = 135;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 132 })?;
+                native.store(target, value);
+                if native.changed(136, 141) {
+                    return native.interpret(136);
+                }
+                // 0136: ADD  [135], [10], [0]
+                let (left, right) = (native.load(136, 135)?, native.load(136, 10)?);
+                let target = 0;
+                let value = i64::checked_add(left, right).ok_or(ComputerError::Overflow { instruction: 136 })?;
+                native.store(target, value);
+                if native.changed(140, 141) {
+                    return native.interpret(140);
+                }
+                // 0140: HLT
+                return Ok(Termination::Halted);
+            },
+            address => return native.interpret(address)
+        };
+    }
+}
@@ -0,0 +1,149 @@
+//! Memory watchpoints and self-modifying code detection.
+use crate::computer::Word;
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchAction {
+    /// Stop after the instruction with `State::Paused`.
+    Pause,
+    /// Only record the event.
+    #[allow(dead_code)]
+    Log
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub addresses: Range<usize>,
+    pub access: Access,
+    pub action: WatchAction
+}
+
+impl Watchpoint {
+
+    pub fn read(addresses: Range<usize>, action: WatchAction) -> Self {
+        Watchpoint { addresses, access: Access::Read, action }
+    }
+
+    pub fn write(addresses: Range<usize>, action: WatchAction) -> Self {
+        Watchpoint { addresses, access: Access::Write, action }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchCause {
+    /// Index of the watchpoint, in the order they were added.
+    Watchpoint(usize),
+    /// A write to an address that has already been executed.
+    SelfModification
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchEvent<W: Word> {
+    /// Address of the instruction making the access.
+    pub instruction: usize,
+    pub address: usize,
+    pub access: Access,
+    pub value: W,
+    pub cause: WatchCause
+}
+
+impl<W: Word> fmt::Display for WatchEvent<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.access, self.cause) {
+            (Access::Read, WatchCause::Watchpoint(index)) =>
+                write!(f, "{:04}: read {} from [{}] (watchpoint {})", self.instruction, self.value, self.address, index),
+            (Access::Write, WatchCause::Watchpoint(index)) =>
+                write!(f, "{:04}: wrote {} to [{}] (watchpoint {})", self.instruction, self.value, self.address, index),
+            (_, WatchCause::SelfModification) =>
+                write!(f, "{:04}: wrote {} over executed code at [{}]", self.instruction, self.value, self.address)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Access, WatchAction, WatchCause, WatchEvent, Watchpoint};
+    use crate::computer::{Machine, State};
+    use crate::program::Program;
+    use std::collections::VecDeque;
+
+    fn machine(source: &str) -> Machine<i64, VecDeque<i64>, Vec<i64>> {
+        let program: Program<i64> = source.parse().unwrap();
+        Machine::from_program(&program, VecDeque::new(), vec![])
+    }
+
+    #[test]
+    fn write_watchpoint_pauses() {
+        let mut machine = machine("1101,1,2,9,1101,3,4,10,99,0,0");
+        machine.add_watchpoint(Watchpoint::write(10..11, WatchAction::Pause));
+
+        assert_eq!(machine.run_until_io().unwrap(), State::Paused);
+        assert_eq!(machine.address(), 8);
+        assert_eq!(machine.take_watch_events(), vec![WatchEvent {
+            instruction: 4,
+            address: 10,
+            access: Access::Write,
+            value: 7,
+            cause: WatchCause::Watchpoint(0)
+        }]);
+
+        assert!(matches!(machine.run_until_io().unwrap(), State::Terminated(_)));
+    }
+
+    #[test]
+    fn read_watchpoint_logs() {
+        let mut machine = machine("1,9,10,11,4,11,2,9,9,3,4,0");
+        machine.add_watchpoint(Watchpoint::read(9..10, WatchAction::Log));
+        machine.add_watchpoint(Watchpoint::read(11..12, WatchAction::Log));
+
+        assert_eq!(machine.run_until_io().unwrap(), State::Output(7));
+
+        let events: Vec<String> = machine.take_watch_events().iter().map(|event| event.to_string()).collect();
+        assert_eq!(events, vec![
+            "0000: read 3 from [9] (watchpoint 0)",
+            "0004: read 7 from [11] (watchpoint 1)"
+        ]);
+
+        // The multiplication reads its watched factor twice.
+        machine.compute().unwrap();
+        assert_eq!(machine.take_watch_events().len(), 2);
+    }
+
+    #[test]
+    fn pause_after_output() {
+        let mut machine = machine("4,3,99,5");
+        machine.add_watchpoint(Watchpoint::read(3..4, WatchAction::Pause));
+
+        assert_eq!(machine.step().unwrap(), State::Output(5));
+        assert_eq!(machine.step().unwrap(), State::Paused);
+        assert!(matches!(machine.step().unwrap(), State::Terminated(_)));
+    }
+
+    #[test]
+    fn self_modifying_code() {
+        // Stores a result as data, then overwrites its first instruction with a halt and jumps back to it.
+        let mut machine = machine("1101,1,1,13,1101,99,0,0,1105,1,0");
+        machine.detect_self_modification(Some(WatchAction::Log));
+
+        machine.compute().unwrap();
+
+        let events: Vec<String> = machine.take_watch_events().iter().map(|event| event.to_string()).collect();
+        assert_eq!(events, vec!["0004: wrote 99 over executed code at [0]"]);
+    }
+
+    #[test]
+    fn data_writes_are_not_self_modification() {
+        let mut machine = machine("1101,1,1,5,99,0");
+        machine.detect_self_modification(Some(WatchAction::Pause));
+
+        assert!(matches!(machine.run_until_io().unwrap(), State::Terminated(_)));
+        assert!(machine.take_watch_events().is_empty());
+    }
+}